@@ -0,0 +1,15 @@
+use ipass::client::Client;
+use ipass::config::Profile;
+use ipass::error::Result;
+use serde_json::json;
+
+use crate::CapabilitiesArgs;
+
+pub async fn capabilities(args: CapabilitiesArgs, profile: &Profile) -> Result<()> {
+    let client = Client::connect(profile, args.port);
+    let capabilities = client.capabilities().await?;
+
+    println!("{}", json!(capabilities));
+
+    Ok(())
+}
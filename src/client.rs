@@ -0,0 +1,706 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use base64::prelude::*;
+use log::{debug, info};
+use num::BigInt;
+use rand::RngCore;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{
+    io,
+    net::{self, UdpSocket, UnixDatagram},
+    time,
+};
+
+use crate::config::{self, PassConfig, Profile};
+use crate::error::{IpassError, Result};
+use crate::secret::{SecretBytes, SecretString};
+use crate::srp;
+use crate::types::auth::*;
+use crate::types::session::Session;
+use crate::types::*;
+use crate::util;
+
+/// How long to wait for the daemon to reply unless configured otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for the first reply before an idempotent request is sent again.
+const INITIAL_RETRANSMIT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Where a daemon listens for requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DaemonAddr {
+    /// A UDP port on a host
+    Udp { host: String, port: u16 },
+    /// A Unix datagram socket on this machine
+    Unix(PathBuf),
+}
+
+impl DaemonAddr {
+    /// Arguments of `start` making a daemon listen here.
+    pub fn start_args(&self) -> String {
+        match self {
+            Self::Udp { port, .. } => format!("--port {}", port),
+            Self::Unix(path) => format!("--socket {}", path.display()),
+        }
+    }
+}
+
+impl fmt::Display for DaemonAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Udp { host, port } => write!(f, "{}:{}", host, port),
+            Self::Unix(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// A request/response channel to the daemon listening on a UDP port or a Unix socket.
+#[derive(Debug, Clone)]
+pub struct DaemonClient {
+    addr: DaemonAddr,
+    timeout: Duration,
+}
+
+impl DaemonClient {
+    pub fn new(host: String, port: u16, timeout: Duration) -> Self {
+        Self::at(DaemonAddr::Udp { host, port }, timeout)
+    }
+
+    pub fn at(addr: DaemonAddr, timeout: Duration) -> Self {
+        Self { addr, timeout }
+    }
+
+    /// Sends `req` to the daemon and waits for its reply.
+    ///
+    /// Idempotent requests are retransmitted with exponential backoff until
+    /// the timeout elapses, other requests are only sent once.
+    pub async fn request(&self, req: &[u8], idempotent: bool) -> Result<Vec<u8>> {
+        let socket = self.connect().await?;
+
+        let deadline = time::Instant::now() + self.timeout;
+        let mut interval = INITIAL_RETRANSMIT_INTERVAL;
+        let mut buf = vec![0; 65536];
+
+        loop {
+            socket
+                .send(req)
+                .await
+                .map_err(|err| self.check_unreachable(err))?;
+
+            let remaining = deadline.saturating_duration_since(time::Instant::now());
+            let wait = if idempotent {
+                interval.min(remaining)
+            } else {
+                remaining
+            };
+
+            match time::timeout(wait, socket.recv(&mut buf)).await {
+                Ok(Ok(len)) => {
+                    buf.truncate(len);
+                    return Ok(buf);
+                }
+                Ok(Err(IpassError::Io(err))) => return Err(self.check_unreachable(err)),
+                Ok(Err(err)) => return Err(err),
+                Err(_) if idempotent && time::Instant::now() < deadline => {
+                    debug!("No reply from daemon after {:?}, retransmitting", wait);
+                    interval *= 2;
+                }
+                Err(_) => return Err(self.unreachable()),
+            }
+        }
+    }
+
+    async fn connect(&self) -> Result<Socket> {
+        match &self.addr {
+            DaemonAddr::Udp { host, port } => {
+                let addr = net::lookup_host((host.as_str(), *port))
+                    .await?
+                    .next()
+                    .ok_or_else(|| self.unreachable())?;
+                let local_addr = if addr.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                };
+                let socket = UdpSocket::bind(local_addr).await?;
+                socket
+                    .connect(addr)
+                    .await
+                    .map_err(|err| self.check_unreachable(err))?;
+                Ok(Socket::Udp(socket, addr))
+            }
+            DaemonAddr::Unix(daemon) => {
+                // Replies need an address to go to, which unbound datagram sockets lack.
+                let local = std::env::temp_dir().join(format!(
+                    "ipass-{}-{:016x}.sock",
+                    std::process::id(),
+                    rand::rng().next_u64()
+                ));
+                let socket = UnixDatagram::bind(&local)?;
+                let connected = socket.connect(daemon);
+                // Wrapped before failing so the bound path is removed.
+                let socket = Socket::Unix(socket, local, daemon.clone());
+                connected.map_err(|err| self.check_unreachable(err))?;
+                Ok(socket)
+            }
+        }
+    }
+
+    fn check_unreachable(&self, err: io::Error) -> IpassError {
+        match err.kind() {
+            io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::NotFound => self.unreachable(),
+            _ => err.into(),
+        }
+    }
+
+    fn unreachable(&self) -> IpassError {
+        IpassError::DaemonUnreachable(self.addr.clone())
+    }
+}
+
+/// A socket connected to the daemon.
+enum Socket {
+    Udp(UdpSocket, SocketAddr),
+    /// Bound to the first path, which is removed when dropped, and connected to the second
+    Unix(UnixDatagram, PathBuf, PathBuf),
+}
+
+impl Socket {
+    async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Udp(socket, _) => socket.send(buf).await,
+            Self::Unix(socket, ..) => socket.send(buf).await,
+        }
+    }
+
+    /// Receives the reply of the daemon. Connected sockets should only see
+    /// the daemon, but don't take a stray datagram for its reply if they do.
+    async fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        match self {
+            Self::Udp(socket, addr) => match socket.recv_from(buf).await? {
+                (len, from) if from == *addr => Ok(len),
+                (_, from) => Err(IpassError::Protocol(format!(
+                    "reply from {} instead of the daemon at {}",
+                    from, addr
+                ))),
+            },
+            Self::Unix(socket, _, daemon) => match socket.recv_from(buf).await? {
+                (len, from) if from.as_pathname() == Some(daemon) => Ok(len),
+                (_, from) => Err(IpassError::Protocol(format!(
+                    "reply from {:?} instead of the daemon at {}",
+                    from,
+                    daemon.display()
+                ))),
+            },
+        }
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        if let Self::Unix(_, local, _) = self {
+            let _ = std::fs::remove_file(local);
+        }
+    }
+}
+
+/// Parses a reply of the helper, telling a rejected session and helper errors apart.
+pub fn parse_response<T: DeserializeOwned>(buf: &[u8]) -> Result<T> {
+    let invalid = |err: serde_json::Error| IpassError::Protocol(err.to_string());
+    let value: serde_json::Value = serde_json::from_slice(buf).map_err(invalid)?;
+    let payload = value.get("payload");
+    let status = payload
+        .and_then(|payload| payload.get("STATUS").or_else(|| payload.get("ErrCode")))
+        .and_then(serde_json::Value::as_i64);
+    if value.get("cmd").and_then(serde_json::Value::as_u64) == Some(Cmd::ReloginNeeded as u64)
+        || status == Some(STATUS_INVALID_SESSION)
+    {
+        return Err(IpassError::SessionRejected);
+    }
+    // Failed requests are answered with a bare status instead of an encrypted message.
+    if let Some(status) = status.filter(|status| *status != 0) {
+        if payload.and_then(|payload| payload.get("SMSG")).is_none() {
+            return Err(IpassError::Helper(status));
+        }
+    }
+
+    serde_json::from_value(value).map_err(invalid)
+}
+
+/// Typed access to the password manager helper behind a daemon.
+///
+/// ```no_run
+/// # async fn example() -> ipass::error::Result<()> {
+/// use ipass::client::Client;
+/// use ipass::config::{ConfigFile, DEFAULT_PROFILE};
+///
+/// let profile = ConfigFile::load()?.profile(DEFAULT_PROFILE)?;
+/// let client = Client::connect(&profile, None).with_session(profile.load_session()?);
+/// for entry in client.get_password("github.com", "octocat").await? {
+///     println!("{}", entry.password.as_str());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Client {
+    daemon: DaemonClient,
+    browser: Browser,
+    tab: Tab,
+    session: Option<Session>,
+}
+
+impl Client {
+    pub fn new(daemon: DaemonClient) -> Self {
+        Self {
+            daemon,
+            browser: Browser::default(),
+            tab: Tab::default(),
+            session: None,
+        }
+    }
+
+    /// Creates a client for the daemon of `profile`, optionally on another port.
+    /// Nothing is sent until the first request.
+    pub fn connect(profile: &Profile, port: Option<u16>) -> Self {
+        Self::new(profile.client(port)).with_browser(profile.browser)
+    }
+
+    /// Sets the browser presented to the helper when authenticating.
+    pub fn with_browser(mut self, browser: Browser) -> Self {
+        self.browser = browser;
+        self
+    }
+
+    /// Sets the browser tab and frame requests claim to come from, 0 and 0 by default.
+    pub fn with_tab(mut self, tab: Tab) -> Self {
+        self.tab = tab;
+        self
+    }
+
+    /// Sets the session established by an earlier [`Client::auth`].
+    pub fn with_session(mut self, session: Session) -> Self {
+        self.session = Some(session);
+        self
+    }
+
+    /// Establishes a new session with the helper, which shows a PIN that `pin`
+    /// must return. The returned session should be stored for later runs.
+    pub async fn auth<F>(&mut self, pin: F) -> Result<PassConfig>
+    where
+        F: FnOnce() -> Result<SecretString>,
+    {
+        let mut buf = [0u8; 16];
+        rand::rng().fill_bytes(&mut buf);
+        let username_b64 = BASE64_STANDARD.encode(buf);
+
+        let pkey = SecretBytes::random(32);
+
+        let pub_key = srp::powmod(
+            &srp::GROUP_GENERATOR,
+            &(BigInt::from_bytes_be(num_bigint::Sign::Plus, &pkey)),
+            &srp::GROUP_PRIME,
+        )
+        .to_bytes_be();
+
+        let pub_b64 = BASE64_STANDARD.encode(&pub_key.1);
+
+        let req = Request {
+            cmd: Cmd::HandShake,
+            msg: Message {
+                qid: "m0".to_owned(),
+                pake: ChallengePake {
+                    tid: username_b64.to_owned(),
+                    msg: MsgType::ClientKeyExchange,
+                    a: pub_b64,
+                    ver: "1.0".to_owned(),
+                    proto: [1].to_vec(),
+                },
+                hstbrsr: self.browser,
+            },
+        };
+        let res: Response<ChallengeMsg> = self.send(&req, false).await?;
+        verify_challenge_response(&res, &username_b64)?;
+
+        let server_pub_key = decode_b64(&res.payload.pake.b)?;
+        let salt = decode_b64(&res.payload.pake.s)?;
+        let password = pin()?;
+        let new_key = srp::pre_master_secret(
+            &pub_key.1,
+            &pkey,
+            &server_pub_key,
+            &username_b64,
+            &password,
+            &salt,
+        );
+
+        let m = srp::compute_m(&username_b64, &salt, &pub_key.1, &server_pub_key, &new_key);
+        let req = Request {
+            cmd: Cmd::HandShake,
+            msg: Message {
+                hstbrsr: self.browser,
+                qid: "m2".to_owned(),
+                pake: VerifyPakeReq {
+                    tid: username_b64.to_owned(),
+                    msg: MsgType::ClientVerification,
+                    m: BASE64_STANDARD.encode(m),
+                },
+            },
+        };
+        let res: Response<VerifyMsg> = self.send(&req, false).await?;
+
+        if res.payload.pake.tid != username_b64 {
+            return Err(invalid_hello("destined to another session"));
+        }
+
+        // The helper can't verify the proof when the PIN was mistyped.
+        if res.payload.pake.error_code.is_some_and(|code| code > 0) {
+            return Err(IpassError::WrongPin);
+        }
+
+        if res.payload.pake.msg != MsgType::ServerVerification {
+            return Err(invalid_hello("unexpected message type"));
+        }
+
+        let session = PassConfig::new(
+            username_b64,
+            SecretString::new(BASE64_STANDARD.encode(&*new_key)),
+        );
+        self.session = Some(Session::new(
+            session.username.clone(),
+            config::session_key(&new_key)?,
+        ));
+        info!("Challenge verified");
+
+        Ok(session)
+    }
+
+    /// Lists the accounts saved for `url`, without their passwords.
+    pub async fn list_logins(&self, url: &str) -> Result<Vec<LoginEntry>> {
+        let session = self.session()?;
+        let req = GetLoginNamesForURLReq {
+            cmd: Cmd::GetLoginNamesForURL,
+            tab: self.tab,
+            url: url.to_owned(),
+            payload: GetLoginNamesForURLPayload {
+                qid: "CmdGetLoginNames4URL".to_owned(),
+                smsg: session.smsg(&ActURL {
+                    act: Action::GhostSearch,
+                    url: url.to_owned(),
+                })?,
+            },
+        };
+        let res: GetLoginNamesForURLRes = self.send(&req, true).await?;
+        let sdata = session.open(&res.payload.smsg)?;
+
+        Ok(sdata.entries.unwrap_or_default())
+    }
+
+    /// Gets the accounts saved for `url` and `username`, with their passwords.
+    pub async fn get_password(&self, url: &str, username: &str) -> Result<Vec<LoginEntry>> {
+        let session = self.session()?;
+        let req = GetLoginNamesForURLReq {
+            cmd: Cmd::GetPasswordForLoginName,
+            tab: self.tab,
+            url: url.to_owned(),
+            payload: GetLoginPasswordForURLPayload {
+                qid: "CmdGetPassword4LoginName".to_owned(),
+                smsg: session.smsg(&ActURLUser {
+                    act: Action::Search,
+                    url: url.to_owned(),
+                    username: username.to_owned(),
+                })?,
+            },
+        };
+        let res: GetLoginPasswordForURLRes = self.send(&req, true).await?;
+        let sdata = session.open(&res.payload.smsg)?;
+
+        Ok(sdata.entries.unwrap_or_default())
+    }
+
+    /// Saves the password of the account for `url` and `username`, creating
+    /// the account if needed.
+    pub async fn save_password(
+        &self,
+        url: &str,
+        username: &str,
+        password: SecretString,
+    ) -> Result<SaveOutcome> {
+        let plan = self.plan_save(url, username).await?;
+        self.apply_save(plan, url, username, password).await
+    }
+
+    /// Finds out what saving a password for `url` and `username` would do,
+    /// without changing anything.
+    pub async fn plan_save(&self, url: &str, username: &str) -> Result<SavePlan> {
+        let session = self.session()?;
+        // The helper may not report accounts it only knows by another url in stage 1.
+        let listed = self
+            .list_logins(url)
+            .await?
+            .iter()
+            .any(|entry| entry.user == username);
+
+        // Stage 1: Save the login name
+        let stage1_req = SaveStage1Req {
+            cmd: Cmd::SaveStage1LoginName,
+            tab: self.tab,
+            payload: SaveStage1Payload {
+                qid: "CmdSaveStage1LoginName".to_owned(),
+                smsg: session.smsg(&SaveStage1Data {
+                    act: Action::Search,
+                    url: url.to_owned(),
+                    username: username.to_owned(),
+                })?,
+            },
+        };
+        let stage1_res: SaveStage1Res = self.send(&stage1_req, false).await?;
+        let stage1 = session.open(&stage1_res.payload.smsg)?;
+        debug!("Stage1 response: {:#?}", stage1);
+        Ok(SavePlan::from_stage1(
+            stage1.status,
+            stage1.requires_user_authentication_to_fill,
+            listed,
+        ))
+    }
+
+    /// Saves the password as planned by [`Client::plan_save`].
+    pub async fn apply_save(
+        &self,
+        plan: SavePlan,
+        url: &str,
+        username: &str,
+        password: SecretString,
+    ) -> Result<SaveOutcome> {
+        let session = self.session()?;
+        let exists = match plan {
+            SavePlan::Create => false,
+            SavePlan::Replace => true,
+            SavePlan::RequiresAuth => return Ok(SaveOutcome::RequiresAuth),
+            SavePlan::Rejected { status } => return Ok(SaveOutcome::Rejected { status }),
+        };
+
+        // Stage 2: Save the password
+        let save_req = SavePasswordReq {
+            cmd: Cmd::NewAccount4URL,
+            tab: self.tab,
+            payload: SavePasswordPayload {
+                qid: "CmdNewAccount4URL".to_owned(),
+                smsg: session.smsg(&SavePasswordData {
+                    act: Action::MaybeAdd,
+                    url: String::new(),
+                    usr: String::new(),
+                    pwd: SecretString::default(),
+                    nurl: url.to_owned(),
+                    nusr: username.to_owned(),
+                    npwd: password,
+                })?,
+            },
+        };
+        let save_res: SavePasswordRes = self.send(&save_req, false).await?;
+        let saved = session.open(&save_res.payload.smsg)?;
+
+        Ok(SaveOutcome::from_status(saved.status, exists))
+    }
+
+    /// The only account saved for `url` with exactly this `username`, with its password.
+    pub async fn account(&self, url: &str, username: &str) -> Result<LoginEntry> {
+        let entries = self
+            .get_password(url, username)
+            .await?
+            .into_iter()
+            .filter(|entry| entry.user == username)
+            .collect();
+        util::single(entries, "account")
+    }
+
+    /// Deletes `account`, found with [`Client::account`] for `url`, and checks
+    /// the helper no longer lists it. The request is not confirmed against the
+    /// browser extension yet, see [`DeleteAccountReq`], so the CLI doesn't offer it.
+    pub async fn delete_account(&self, url: &str, account: LoginEntry) -> Result<DeleteOutcome> {
+        let session = self.session()?;
+        let site = account.site_for(url).to_owned();
+        let user = account.user.clone();
+        let req = DeleteAccountReq {
+            cmd: Cmd::NewAccount4URL,
+            tab: self.tab,
+            payload: DeleteAccountPayload {
+                qid: "CmdNewAccount4URL".to_owned(),
+                smsg: session.smsg(&DeleteAccountData::new(&site, account))?,
+            },
+        };
+        let res: SavePasswordRes = self.send(&req, false).await?;
+        let deleted = session.open(&res.payload.smsg)?;
+        if deleted.status != STATUS_SUCCESS {
+            return Ok(DeleteOutcome::from_reply(deleted.status, false));
+        }
+
+        let still_listed = self
+            .list_logins(&site)
+            .await?
+            .iter()
+            .any(|entry| entry.user == user);
+        Ok(DeleteOutcome::from_reply(deleted.status, still_listed))
+    }
+
+    /// Changes the url, user name or password of the only account saved for
+    /// `url` and `username`.
+    pub async fn update_account(
+        &self,
+        url: &str,
+        username: &str,
+        update: AccountUpdate,
+    ) -> Result<UpdateOutcome> {
+        let session = self.session()?;
+        let current = self.account(url, username).await?;
+        let site = current.site_for(url).to_owned();
+
+        let changed = update.changes(&site, &current);
+        if changed.is_empty() {
+            return Ok(UpdateOutcome::Unchanged);
+        }
+        let nurl = update.url.unwrap_or_else(|| site.clone());
+        let nusr = update.username.unwrap_or_else(|| current.user.clone());
+        let npwd = update.password.unwrap_or_else(|| current.password.clone());
+
+        let req = SavePasswordReq {
+            cmd: Cmd::NewAccount4URL,
+            tab: self.tab,
+            payload: SavePasswordPayload {
+                qid: "CmdNewAccount4URL".to_owned(),
+                smsg: session.smsg(&SavePasswordData {
+                    act: Action::Update,
+                    url: site,
+                    usr: current.user,
+                    pwd: current.password,
+                    nurl,
+                    nusr,
+                    npwd,
+                })?,
+            },
+        };
+        let res: SavePasswordRes = self.send(&req, false).await?;
+        let updated = session.open(&res.payload.smsg)?;
+
+        Ok(match updated.status {
+            STATUS_SUCCESS => UpdateOutcome::Updated { changed },
+            status => UpdateOutcome::Rejected { status },
+        })
+    }
+
+    /// Gets the one time codes for `url`, which may be given without a scheme.
+    pub async fn get_otp(&self, url: &str) -> Result<Vec<OTPEntry>> {
+        let session = self.session()?;
+        let url = if url.starts_with("http://") || url.starts_with("https://") {
+            url.to_owned()
+        } else {
+            format!("http://{}", url)
+        };
+        let req = GetOTPReq {
+            cmd: Cmd::DidFillOneTimeCode,
+            tab: self.tab,
+            payload: GetOTPForURLPayload {
+                qid: "CmdDidFillOneTimeCode".to_owned(),
+                smsg: session.smsg(&ActFrameURLsType {
+                    act: Action::Search,
+                    urls: vec![url],
+                    typ: "oneTimeCodes".to_owned(),
+                })?,
+            },
+        };
+        let res: GetOTPForURLRes = self.send(&req, true).await?;
+        let sdata = session.open(&res.payload.smsg)?;
+
+        Ok(sdata.entries.unwrap_or_default())
+    }
+
+    /// Asks the helper what it supports, which needs no session.
+    pub async fn capabilities(&self) -> Result<Capabilities> {
+        let req = CmdReq {
+            cmd: Cmd::GetCapabilities,
+            tab: self.tab,
+        };
+        let res: CapabilitiesRes = self.send(&req, true).await?;
+
+        Ok(res.payload)
+    }
+
+    fn session(&self) -> Result<&Session> {
+        self.session.as_ref().ok_or(IpassError::NotAuthenticated)
+    }
+
+    /// Sends `req` and parses the reply, which must answer the same command.
+    async fn send<T: DeserializeOwned>(&self, req: &impl Serialize, idempotent: bool) -> Result<T> {
+        let req = serde_json::to_value(req)?;
+        let buf = self
+            .daemon
+            .request(req.to_string().as_bytes(), idempotent)
+            .await?;
+        debug!("Received response: {}", String::from_utf8_lossy(&buf));
+        let res: Value = parse_response(&buf)?;
+        if res.get("cmd") != req.get("cmd") {
+            match Notification::deserialize(&res) {
+                Ok(Notification::PasswordsDisabled) => {
+                    return Err(IpassError::PermissionDenied(
+                        "password filling is turned off in the password manager".to_owned(),
+                    ))
+                }
+                Ok(notification) => {
+                    return Err(IpassError::Protocol(format!(
+                        "notification {:?} instead of a reply to command {}",
+                        notification.cmd(),
+                        req["cmd"]
+                    )))
+                }
+                Err(_) => {}
+            }
+            return Err(IpassError::Protocol(format!(
+                "reply to command {} instead of {}",
+                res.get("cmd").unwrap_or(&Value::Null),
+                req["cmd"]
+            )));
+        }
+        serde_json::from_value(res).map_err(|err| IpassError::Protocol(err.to_string()))
+    }
+}
+
+fn verify_challenge_response(response: &Response<ChallengeMsg>, username_b64: &str) -> Result<()> {
+    if response.payload.pake.tid != username_b64 {
+        return Err(invalid_hello("destined to another session"));
+    }
+    if let Some(error_code) = response.payload.pake.error_code.filter(|code| *code > 0) {
+        return Err(IpassError::Helper(error_code.into()));
+    }
+    if response.payload.pake.msg != MsgType::ServerKeyExchange {
+        return Err(invalid_hello("unexpected message type"));
+    }
+    if response.payload.pake.proto != SecretSessionVersion::SrpWithRfcVerification {
+        return Err(invalid_hello("unsupported protocol"));
+    }
+    if response
+        .payload
+        .pake
+        .version
+        .as_ref()
+        .is_some_and(|version| version != "1.0")
+    {
+        return Err(invalid_hello("unsupported version"));
+    }
+    Ok(())
+}
+
+fn invalid_hello(reason: &str) -> IpassError {
+    IpassError::Protocol(format!("invalid server hello: {}", reason))
+}
+
+fn decode_b64(value: &str) -> Result<Vec<u8>> {
+    BASE64_STANDARD
+        .decode(value)
+        .map_err(|err| IpassError::Protocol(format!("invalid server hello: {}", err)))
+}
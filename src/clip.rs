@@ -0,0 +1,256 @@
+use std::env;
+use std::fs::OpenOptions;
+use std::io::{self, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use base64::prelude::*;
+use ipass::config::Settings;
+use ipass::error::{IpassError, Result};
+use log::debug;
+use zeroize::Zeroizing;
+
+use crate::ClipClearArgs;
+
+/// How values get into the clipboard and back out.
+#[derive(Debug)]
+struct Clipboard {
+    /// Shell command reading the value from its input, OSC 52 if not set.
+    copy: Option<String>,
+    /// Shell command printing the clipboard, if there is a way to read it.
+    paste: Option<String>,
+}
+
+impl Clipboard {
+    fn new(settings: &Settings) -> Self {
+        let paste = settings.clip_paste_command.clone();
+        match &settings.clip_command {
+            Some(copy) => Self {
+                copy: Some(copy.clone()),
+                paste,
+            },
+            // Over SSH the Mac's pasteboard is not the one in front of the user.
+            None if cfg!(target_os = "macos") && env::var_os("SSH_CONNECTION").is_none() => Self {
+                copy: Some("pbcopy".to_owned()),
+                paste: paste.or_else(|| Some("pbpaste".to_owned())),
+            },
+            None => Self { copy: None, paste },
+        }
+    }
+
+    fn copy(&self, value: &str) -> Result<()> {
+        match &self.copy {
+            Some(command) => {
+                let mut child = Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::null())
+                    .spawn()?;
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(value.as_bytes())?;
+                }
+                check_status(command, child.wait()?)
+            }
+            None => self.osc52(value),
+        }
+    }
+
+    /// Asks the terminal to set its clipboard, which works through SSH and, when
+    /// passed through, tmux.
+    fn osc52(&self, value: &str) -> Result<()> {
+        let mut tty = OpenOptions::new()
+            .write(true)
+            .open("/dev/tty")
+            .map_err(|err| {
+                IpassError::InvalidInput(format!(
+                    "no terminal to copy to with OSC 52 ({}), please set the `clip_command` setting",
+                    err
+                ))
+            })?;
+        let sequence = Zeroizing::new(format!("\x1b]52;c;{}\x07", BASE64_STANDARD.encode(value)));
+        if env::var_os("TMUX").is_some() {
+            let escaped = Zeroizing::new(sequence.replace('\x1b', "\x1b\x1b"));
+            write!(tty, "\x1bPtmux;{}\x1b\\", escaped.as_str())?;
+        } else {
+            tty.write_all(sequence.as_bytes())?;
+        }
+        Ok(tty.flush()?)
+    }
+
+    /// The current content of the clipboard, `None` if it can't be read.
+    fn paste(&self) -> Result<Option<Zeroizing<String>>> {
+        let Some(command) = &self.paste else {
+            return Ok(None);
+        };
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::null())
+            .output()?;
+        let stdout = Zeroizing::new(output.stdout);
+        check_status(command, output.status)?;
+        Ok(Some(Zeroizing::new(
+            String::from_utf8_lossy(&stdout).into_owned(),
+        )))
+    }
+}
+
+fn check_status(command: &str, status: std::process::ExitStatus) -> Result<()> {
+    if status.success() {
+        Ok(())
+    } else {
+        Err(IpassError::Io(io::Error::other(format!(
+            "clipboard command `{}` failed with {}",
+            command, status
+        ))))
+    }
+}
+
+/// Copies `value` to the clipboard, and leaves a process behind that clears it
+/// after the `clip_timeout` setting if the clipboard can be read to check it.
+pub fn copy(value: &str, settings: &Settings) -> Result<()> {
+    let clipboard = Clipboard::new(settings);
+    clipboard.copy(value)?;
+
+    let timeout = settings.clip_timeout();
+    if timeout.is_zero() {
+        eprintln!("Copied to the clipboard");
+        return Ok(());
+    }
+    if clipboard.paste.is_none() {
+        eprintln!(
+            "Copied to the clipboard, which is not cleared automatically as it can't be read, please set the `clip_paste_command` setting"
+        );
+        return Ok(());
+    }
+
+    let mut command = Command::new(env::current_exe()?);
+    command
+        .arg("clip-clear")
+        .arg("--after")
+        .arg(timeout.as_secs().to_string());
+    if let Some(copy) = &clipboard.copy {
+        command.arg("--copy-command").arg(copy);
+    }
+    if let Some(paste) = &clipboard.paste {
+        command.arg("--paste-command").arg(paste);
+    }
+    // Its own process group keeps it alive when the shell interrupts the foreground job.
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(value.as_bytes())?;
+    }
+    eprintln!(
+        "Copied to the clipboard, clearing it in {} seconds",
+        timeout.as_secs()
+    );
+
+    Ok(())
+}
+
+/// Clears the clipboard after a while, unless something else was copied meanwhile.
+/// The copied value is read from the standard input.
+pub async fn clear(args: ClipClearArgs) -> Result<()> {
+    let mut value = Zeroizing::new(String::new());
+    io::stdin().read_to_string(&mut value)?;
+    tokio::time::sleep(Duration::from_secs(args.after)).await;
+
+    let clipboard = Clipboard {
+        copy: args.copy_command,
+        paste: args.paste_command,
+    };
+    clear_if_unchanged(&clipboard, &value)
+}
+
+fn clear_if_unchanged(clipboard: &Clipboard, value: &str) -> Result<()> {
+    let current = clipboard.paste()?;
+    if !should_clear(current.as_deref().map(String::as_str), value) {
+        debug!("Clipboard can't be read or changed, leaving it alone");
+        return Ok(());
+    }
+    clipboard.copy("")
+}
+
+/// Whether the clipboard still holds the copied `value`. What can't be read
+/// might be something the user copied since, so it is never cleared.
+fn should_clear(current: Option<&str>, value: &str) -> bool {
+    current.is_some_and(|current| current.trim_end_matches('\n') == value.trim_end_matches('\n'))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+
+    /// A clipboard kept in a file of a fresh directory.
+    fn file_clipboard(name: &str) -> (Clipboard, PathBuf) {
+        let dir = env::temp_dir().join(format!("ipass-clip-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).unwrap();
+        let path = dir.join("clipboard");
+        let clipboard = Clipboard {
+            copy: Some(format!("cat > '{}'", path.display())),
+            paste: Some(format!("cat '{}'", path.display())),
+        };
+        (clipboard, path)
+    }
+
+    fn read(path: &Path) -> String {
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn test_should_clear() {
+        assert!(should_clear(Some("hunter2"), "hunter2"));
+        assert!(should_clear(Some("hunter2\n"), "hunter2"));
+        assert!(!should_clear(Some("something else"), "hunter2"));
+        assert!(!should_clear(None, "hunter2"));
+    }
+
+    #[test]
+    fn test_copy_and_paste() {
+        let (clipboard, path) = file_clipboard("round-trip");
+        clipboard.copy("hunter2").unwrap();
+        assert_eq!(read(&path), "hunter2");
+        assert_eq!(clipboard.paste().unwrap().unwrap().as_str(), "hunter2");
+    }
+
+    #[test]
+    fn test_clear_only_the_copied_value() {
+        let (clipboard, path) = file_clipboard("clear");
+        clipboard.copy("hunter2").unwrap();
+        clear_if_unchanged(&clipboard, "hunter2").unwrap();
+        assert_eq!(read(&path), "");
+
+        clipboard.copy("copied since").unwrap();
+        clear_if_unchanged(&clipboard, "hunter2").unwrap();
+        assert_eq!(read(&path), "copied since");
+    }
+
+    #[test]
+    fn test_unreadable_clipboard_is_not_cleared() {
+        let (mut clipboard, path) = file_clipboard("unreadable");
+        clipboard.copy("hunter2").unwrap();
+        clipboard.paste = None;
+        clear_if_unchanged(&clipboard, "hunter2").unwrap();
+        assert_eq!(read(&path), "hunter2");
+    }
+
+    #[test]
+    fn test_failing_command_is_an_error() {
+        let clipboard = Clipboard {
+            copy: Some("exit 3".to_owned()),
+            paste: Some("exit 3".to_owned()),
+        };
+        assert!(matches!(clipboard.copy("hunter2"), Err(IpassError::Io(_))));
+        assert!(matches!(clipboard.paste(), Err(IpassError::Io(_))));
+    }
+}
@@ -1,58 +1,781 @@
+use log::{info, warn};
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
 use tokio::io;
+use zeroize::Zeroizing;
 
-#[allow(deprecated)]
-use std::env::home_dir;
+use std::collections::BTreeMap;
+use std::fs::{DirBuilder, OpenOptions, Permissions};
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
 
-use base64::prelude::*;
+use crate::client::{DaemonAddr, DaemonClient, DEFAULT_TIMEOUT};
+use crate::error::{IpassError, Result};
+use crate::keywrap::{self, KeyProtection};
+use crate::output::OutputFormat;
+use crate::secret::{SecretBytes, SecretString};
+use crate::types::session::Session;
+use crate::types::Browser;
+use crate::{paths, util, DEFAULT_PORT};
 
-use crate::util;
-#[derive(Debug, Deserialize, Serialize)]
+pub const DEFAULT_PROFILE: &str = "default";
+
+const DEFAULT_HOST: &str = "127.0.0.1";
+
+/// How long a copied secret stays in the clipboard unless configured otherwise.
+const DEFAULT_CLIP_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// Version of the config file layout written by this build.
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Unwrapped session key, cached so a passphrase is asked at most once per process.
+static UNWRAPPED_KEY: Mutex<Option<(SecretString, SecretBytes)>> = Mutex::new(None);
+
+/// The session established with a daemon by `ipass auth`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct PassConfig {
     pub username: String,
-    pub shared_key: String,
+    pub shared_key: SecretString,
+    #[serde(default)]
+    pub protection: KeyProtection,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub salt: Option<String>,
 }
 
 impl PassConfig {
-    pub fn new(username: String, shared_key: String) -> Self {
+    pub fn new(username: String, shared_key: SecretString) -> Self {
         Self {
             username,
             shared_key,
+            protection: KeyProtection::None,
+            salt: None,
         }
     }
 
-    pub fn save(&self) {
-        #[allow(deprecated)]
-        let path = home_dir().unwrap().join(".ipass");
-        std::fs::create_dir_all(&path).expect("Failed to create ipass config directory");
-        let path = path.join("config.json");
-        let content = serde_json::to_string(self).expect("Failed to serialize config");
-        std::fs::write(path, content).expect("Failed to write to config file");
+    /// Re-encrypts the (so far plain) shared key with the given protection,
+    /// keychain keys are stored for the named profile.
+    pub fn protect(&mut self, protection: KeyProtection, profile: &str) -> Result<()> {
+        let key = keywrap::unwrap(
+            &self.shared_key,
+            self.protection,
+            self.salt.as_deref(),
+            profile,
+        )?;
+        (self.shared_key, self.salt) = keywrap::wrap(&key, protection, profile)?;
+        self.protection = protection;
+        Ok(())
+    }
+
+    /// The session to encrypt requests with, of the named profile.
+    pub fn session(&self, profile: &str) -> Result<Session> {
+        Ok(Session::new(
+            self.username.clone(),
+            self.decryption_key(profile)?,
+        ))
     }
 
-    pub fn decryption_key(&self) -> [u8; 16] {
-        let shared_key = BASE64_STANDARD.decode(self.shared_key.as_str()).unwrap();
-        shared_key[..16].try_into().unwrap()
+    pub fn decryption_key(&self, profile: &str) -> Result<Zeroizing<[u8; 16]>> {
+        let mut cache = UNWRAPPED_KEY.lock().unwrap_or_else(|err| err.into_inner());
+        let shared_key = match cache.as_ref() {
+            Some((wrapped, key)) if *wrapped == self.shared_key => key.clone(),
+            _ => {
+                let key = keywrap::unwrap(
+                    &self.shared_key,
+                    self.protection,
+                    self.salt.as_deref(),
+                    profile,
+                )?;
+                *cache = Some((self.shared_key.clone(), key.clone()));
+                key
+            }
+        };
+        session_key(&shared_key)
     }
 }
 
+/// The key requests are encrypted with, the first 16 bytes of the shared key.
+pub(crate) fn session_key(shared_key: &[u8]) -> Result<Zeroizing<[u8; 16]>> {
+    shared_key
+        .get(..16)
+        .and_then(|key| key.try_into().ok())
+        .map(Zeroizing::new)
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "session key is too short").into()
+        })
+}
+
 impl PassConfig {
-    pub fn load() -> io::Result<Self> {
-        #[allow(deprecated)]
-        let path = home_dir().unwrap().join(".ipass/config.json");
-        let content = std::fs::read_to_string(path)?;
-        let config: Self =
-            serde_json::from_str(&content).expect("parse config file in JSON format");
-        if config.shared_key.is_empty() {
-            let ipass_cli = util::my_cli();
+    /// Loads the session of the named profile.
+    pub fn load(profile: &str) -> Result<Self> {
+        let session = ConfigFile::load()?
+            .profiles
+            .remove(profile)
+            .and_then(|profile| profile.session)
+            .filter(|session| !session.shared_key.is_empty());
+        session.ok_or(IpassError::NotAuthenticated)
+    }
+}
+
+/// Defaults for every command, kept in the config file and overridable per profile.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Settings {
+    /// Host the daemon runs on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// Port the daemon listens on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Unix socket the local daemon listens on, used instead of the host and port
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub socket: Option<PathBuf>,
+    /// Seconds to wait for the daemon to reply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    /// Format of the command output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<OutputFormat>,
+    /// Password manager helper executable, found from the browser manifests by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub helper: Option<PathBuf>,
+    /// Shell command copying its input to the clipboard, OSC 52 or `pbcopy` by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clip_command: Option<String>,
+    /// Shell command printing the clipboard, to check it before clearing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clip_paste_command: Option<String>,
+    /// Seconds after which a copied secret is cleared from the clipboard, 0 to keep it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clip_timeout: Option<u64>,
+    /// User name to use per domain when none is given
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub usernames: BTreeMap<String, String>,
+}
+
+impl Settings {
+    /// The built-in defaults, used when neither the command line nor the config file has a value.
+    pub fn builtin() -> Self {
+        Self {
+            host: Some(DEFAULT_HOST.to_owned()),
+            port: Some(DEFAULT_PORT),
+            timeout: Some(DEFAULT_TIMEOUT.as_secs()),
+            output: Some(OutputFormat::default()),
+            clip_timeout: Some(DEFAULT_CLIP_TIMEOUT.as_secs()),
+            ..Self::default()
+        }
+    }
+
+    /// Fills the values not set here from `defaults`.
+    pub fn or(mut self, defaults: &Settings) -> Self {
+        self.host = self.host.or_else(|| defaults.host.clone());
+        self.port = self.port.or(defaults.port);
+        self.socket = self.socket.or_else(|| defaults.socket.clone());
+        self.timeout = self.timeout.or(defaults.timeout);
+        self.output = self.output.or(defaults.output);
+        self.helper = self.helper.or_else(|| defaults.helper.clone());
+        self.clip_command = self.clip_command.or_else(|| defaults.clip_command.clone());
+        self.clip_paste_command = self
+            .clip_paste_command
+            .or_else(|| defaults.clip_paste_command.clone());
+        self.clip_timeout = self.clip_timeout.or(defaults.clip_timeout);
+        for (domain, username) in &defaults.usernames {
+            self.usernames
+                .entry(domain.clone())
+                .or_insert_with(|| username.clone());
+        }
+        self
+    }
+
+    pub fn host(&self) -> &str {
+        self.host.as_deref().unwrap_or(DEFAULT_HOST)
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(DEFAULT_PORT)
+    }
+
+    /// Where the daemon listens, a port given on the command line wins over the socket.
+    pub fn daemon_addr(&self, port: Option<u16>) -> DaemonAddr {
+        match (port, &self.socket) {
+            (None, Some(socket)) => DaemonAddr::Unix(socket.clone()),
+            _ => DaemonAddr::Udp {
+                host: self.host().to_owned(),
+                port: port.unwrap_or(self.port()),
+            },
+        }
+    }
+
+    pub fn is_local(&self) -> bool {
+        matches!(self.host(), "127.0.0.1" | "localhost" | "::1")
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout.map_or(DEFAULT_TIMEOUT, Duration::from_secs)
+    }
+
+    pub fn output(&self) -> OutputFormat {
+        self.output.unwrap_or_default()
+    }
+
+    pub fn clip_timeout(&self) -> Duration {
+        self.clip_timeout
+            .map_or(DEFAULT_CLIP_TIMEOUT, Duration::from_secs)
+    }
+
+    /// The default user name for the domain of `url`, also matching its parent domains.
+    pub fn username_for(&self, url: &str) -> Option<&str> {
+        let mut domain = util::domain(url);
+        loop {
+            if let Some(username) = self.usernames.get(domain) {
+                return Some(username);
+            }
+            domain = domain.split_once('.')?.1;
+        }
+    }
+}
+
+/// How to reach one daemon, and the session established with it.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Profile {
+    /// Name of the profile, set by [`ConfigFile::profile`]
+    #[serde(skip)]
+    pub name: String,
+    /// Settings of this profile, falling back to the ones of the config file
+    #[serde(flatten)]
+    pub settings: Settings,
+    #[serde(default)]
+    pub browser: Browser,
+    /// Kept in the sessions file, legacy config files have it inline
+    #[serde(default, skip_serializing)]
+    pub session: Option<PassConfig>,
+}
+
+impl Profile {
+    /// Loads the current session of this profile, which may have been
+    /// replaced by authenticating again since the profile was loaded.
+    pub fn load_session(&self) -> Result<Session> {
+        PassConfig::load(&self.name)?.session(&self.name)
+    }
+
+    /// Creates a client for this profile's daemon, optionally on another port.
+    pub fn client(&self, port: Option<u16>) -> DaemonClient {
+        DaemonClient::at(self.settings.daemon_addr(port), self.settings.timeout())
+    }
+}
+
+/// Layout of the config file before profiles were introduced.
+#[derive(Debug, Deserialize)]
+struct LegacyConfig {
+    #[serde(flatten)]
+    session: PassConfig,
+    #[serde(default)]
+    browser: Browser,
+}
+
+/// Settings and profiles stored in the config file, and sessions kept in the sessions file.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ConfigFile {
+    /// Layout version of the config file, see [`CONFIG_VERSION`]
+    #[serde(default = "config_version")]
+    pub version: u32,
+    #[serde(default)]
+    pub settings: Settings,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Default for ConfigFile {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            settings: Settings::default(),
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+fn config_version() -> u32 {
+    CONFIG_VERSION
+}
+
+impl ConfigFile {
+    pub fn load() -> Result<Self> {
+        let path = paths::config_file()?;
+        let mut config = match read_private(&path)? {
+            Some(content) => Self::parse(&path, &content)?,
+            None => match Self::migrate_legacy()? {
+                Some(config) => return Ok(config),
+                None => Self::default(),
+            },
+        };
+
+        let sessions_path = paths::sessions_file()?;
+        let sessions: BTreeMap<String, PassConfig> = match read_private(&sessions_path)? {
+            Some(content) => {
+                serde_json::from_str(&content).map_err(|err| invalid(&sessions_path, err))?
+            }
+            None => BTreeMap::new(),
+        };
+        for (name, session) in sessions {
+            if name == DEFAULT_PROFILE || config.profiles.contains_key(&name) {
+                config.profile_mut(&name).session = Some(session);
+            }
+        }
+        Ok(config)
+    }
+
+    fn parse(path: &Path, content: &str) -> Result<Self> {
+        let value: serde_json::Value =
+            serde_json::from_str(content).map_err(|err| invalid(path, err))?;
+        let version = value
+            .get("version")
+            .and_then(serde_json::Value::as_u64)
+            .unwrap_or(CONFIG_VERSION.into());
+        if version > CONFIG_VERSION.into() {
             return Err(io::Error::new(
-                io::ErrorKind::NotConnected,
+                io::ErrorKind::InvalidData,
                 format!(
-                    "session is not authenticated, please run `{} auth` to authenticate",
-                    ipass_cli
+                    "config file {} has version {}, which is newer than the supported version {}, please upgrade",
+                    path.display(),
+                    version,
+                    CONFIG_VERSION
                 ),
-            ));
+            )
+            .into());
         }
-        Ok(config)
+        serde_json::from_value(value).map_err(|err| invalid(path, err))
+    }
+
+    /// Moves the config file from `~/.ipass`, splitting sessions from profiles.
+    fn migrate_legacy() -> Result<Option<Self>> {
+        let legacy_path = paths::legacy_config_file()?;
+        let Some(content) = read_private(&legacy_path)? else {
+            return Ok(None);
+        };
+
+        let mut config = Self::parse_legacy(&legacy_path, &content)?;
+        config.save()?;
+
+        std::fs::remove_file(&legacy_path)?;
+        if let Some(dir) = legacy_path.parent() {
+            // Only succeeds when nothing else was kept there.
+            let _ = std::fs::remove_dir(dir);
+        }
+        info!(
+            "Migrated config file {} to {}",
+            legacy_path.display(),
+            paths::config_file()?.display()
+        );
+        Ok(Some(config))
+    }
+
+    /// Reads a config file from `~/.ipass`, where the session used to be
+    /// stored inline before profiles were introduced.
+    fn parse_legacy(path: &Path, content: &str) -> Result<Self> {
+        let value: serde_json::Value =
+            serde_json::from_str(content).map_err(|err| invalid(path, err))?;
+        if value.get("profiles").is_some() {
+            return Self::parse(path, content);
+        }
+
+        let legacy: LegacyConfig =
+            serde_json::from_value(value).map_err(|err| invalid(path, err))?;
+        let profile = Profile {
+            browser: legacy.browser,
+            session: Some(legacy.session).filter(|session| !session.shared_key.is_empty()),
+            ..Profile::default()
+        };
+        Ok(Self {
+            profiles: BTreeMap::from([(DEFAULT_PROFILE.to_owned(), profile)]),
+            ..Self::default()
+        })
+    }
+
+    pub fn save(&mut self) -> Result<()> {
+        self.version = CONFIG_VERSION;
+        let sessions = self
+            .profiles
+            .iter()
+            .filter_map(|(name, profile)| Some((name, profile.session.as_ref()?)))
+            .collect::<BTreeMap<_, _>>();
+        let (config_path, sessions_path) = (paths::config_file()?, paths::sessions_file()?);
+        if !paths::is_config_file_overridden() {
+            for dir in [&config_path, &sessions_path]
+                .into_iter()
+                .filter_map(|path| path.parent())
+            {
+                create_private_dir(dir)?;
+            }
+        }
+        write_private(&config_path, &serde_json::to_string(self)?)?;
+        write_private(&sessions_path, &serde_json::to_string(&sessions)?)
+    }
+
+    /// Returns the named profile with the settings of the config file filled in,
+    /// the default profile always exists.
+    pub fn profile(&self, name: &str) -> Result<Profile> {
+        match self.profiles.get(name) {
+            Some(profile) => Ok(self.resolve(name, profile.clone())),
+            None if name == DEFAULT_PROFILE => Ok(self.resolve(name, Profile::default())),
+            None => Err(IpassError::NotFound(format!(
+                "profile `{}` does not exist",
+                name
+            ))),
+        }
+    }
+
+    fn resolve(&self, name: &str, mut profile: Profile) -> Profile {
+        profile.name = name.to_owned();
+        profile.settings = profile.settings.or(&self.settings);
+        profile
+    }
+
+    pub fn profile_mut(&mut self, name: &str) -> &mut Profile {
+        self.profiles.entry(name.to_owned()).or_default()
+    }
+
+    /// Forgets the sessions of all profiles pointing to the local daemon at `addr`.
+    pub fn clear_sessions(&mut self, addr: &DaemonAddr) {
+        let settings = self.settings.clone();
+        self.profiles
+            .values_mut()
+            .filter(|profile| {
+                let resolved = profile.settings.clone().or(&settings);
+                match (resolved.daemon_addr(None), addr) {
+                    (
+                        DaemonAddr::Udp { port, .. },
+                        DaemonAddr::Udp {
+                            port: listening, ..
+                        },
+                    ) => resolved.is_local() && port == *listening,
+                    (profile_addr, addr) => profile_addr == *addr,
+                }
+            })
+            .for_each(|profile| profile.session = None);
+    }
+}
+
+/// Reads a file only the current user may access, `None` if it doesn't exist.
+fn read_private(path: &Path) -> Result<Option<String>> {
+    check_permissions(path)?;
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn invalid(path: &Path, err: serde_json::Error) -> IpassError {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid file {}: {}", path.display(), err),
+    )
+    .into()
+}
+
+/// Creates a directory of ipass only the current user may access.
+fn create_private_dir(dir: &Path) -> Result<()> {
+    DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    Ok(std::fs::set_permissions(
+        dir,
+        Permissions::from_mode(0o700),
+    )?)
+}
+
+/// Writes a file only the current user may access, leaving its directory as is.
+/// The content goes to a temporary file next to it first, which then replaces
+/// the file, so an interrupted write never leaves it empty or half written.
+fn write_private(path: &Path, content: &str) -> Result<()> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| IpassError::InvalidInput(format!("{} is not a file", path.display())))?;
+    let temp = path.with_file_name(format!(
+        ".{}.{}-{:x}.tmp",
+        name.to_string_lossy(),
+        std::process::id(),
+        rand::rng().next_u32()
+    ));
+    let written = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&temp)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&temp, path)
+    })();
+    if let Err(err) = written {
+        let _ = std::fs::remove_file(&temp);
+        return Err(err.into());
+    }
+    // Makes the rename itself durable, which not every file system allows.
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if let Err(err) = std::fs::File::open(dir).and_then(|dir| dir.sync_all()) {
+        warn!("Failed to sync {}: {}", dir.display(), err);
+    }
+    Ok(())
+}
+
+/// Refuses files other users can access, and warns about such directories.
+fn check_permissions(path: &Path) -> Result<()> {
+    // The parent of a bare file name is empty.
+    let dir = path.parent().map(|dir| {
+        if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        }
+    });
+    if let Some(dir) = dir {
+        if let Ok(metadata) = std::fs::metadata(dir) {
+            if metadata.permissions().mode() & 0o077 != 0 {
+                warn!(
+                    "Directory {} is accessible by other users, run `chmod 700 {}`",
+                    dir.display(),
+                    dir.display()
+                );
+            }
+        }
+    }
+    if let Ok(metadata) = std::fs::metadata(path) {
+        if metadata.permissions().mode() & 0o077 != 0 {
+            return Err(IpassError::PermissionDenied(format!(
+                "file {} is accessible by other users, run `chmod 600 {}` and re-authenticate",
+                path.display(),
+                path.display()
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for one test, only the current user may access it.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ipass-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        DirBuilder::new().mode(0o700).create(&dir).unwrap();
+        dir
+    }
+
+    fn write_with_mode(path: &Path, mode: u32) {
+        std::fs::write(path, "{}").unwrap();
+        std::fs::set_permissions(path, Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn test_private_file_is_read() {
+        let dir = temp_dir("private-file");
+        let path = dir.join("config.json");
+        assert_eq!(read_private(&path).unwrap(), None);
+        write_with_mode(&path, 0o600);
+        assert_eq!(read_private(&path).unwrap().as_deref(), Some("{}"));
+    }
+
+    #[test]
+    fn test_file_readable_by_others_is_refused() {
+        let dir = temp_dir("shared-file");
+        let path = dir.join("config.json");
+        for mode in [0o644, 0o640, 0o606] {
+            write_with_mode(&path, mode);
+            assert!(
+                matches!(read_private(&path), Err(IpassError::PermissionDenied(_))),
+                "{:o}",
+                mode
+            );
+        }
+    }
+
+    fn usernames(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|(domain, username)| (domain.to_string(), username.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_username_for_walks_up_parent_domains() {
+        let settings = Settings {
+            usernames: usernames(&[("docker.com", "whale"), ("hub.docker.com", "captain")]),
+            ..Settings::default()
+        };
+        assert_eq!(settings.username_for("docker.com"), Some("whale"));
+        assert_eq!(
+            settings.username_for("https://login.docker.com/x"),
+            Some("whale")
+        );
+        assert_eq!(settings.username_for("hub.docker.com"), Some("captain"));
+        assert_eq!(
+            settings.username_for("eu.hub.docker.com:443"),
+            Some("captain")
+        );
+        assert_eq!(settings.username_for("notdocker.com"), None);
+        assert_eq!(settings.username_for("com"), None);
+        assert_eq!(settings.username_for(""), None);
+    }
+
+    #[test]
+    fn test_settings_fall_back_to_defaults() {
+        let profile = Settings {
+            port: Some(27390),
+            usernames: usernames(&[("docker.com", "profile")]),
+            ..Settings::default()
+        };
+        let file = Settings {
+            port: Some(27391),
+            timeout: Some(10),
+            clip_command: Some("xclip".to_owned()),
+            usernames: usernames(&[("docker.com", "file"), ("github.com", "octocat")]),
+            ..Settings::default()
+        };
+
+        let settings = profile.or(&file).or(&Settings::builtin());
+        assert_eq!(settings.port, Some(27390));
+        assert_eq!(settings.timeout, Some(10));
+        assert_eq!(settings.clip_command.as_deref(), Some("xclip"));
+        assert_eq!(settings.host.as_deref(), Some(DEFAULT_HOST));
+        assert_eq!(settings.clip_timeout(), DEFAULT_CLIP_TIMEOUT);
+        assert_eq!(settings.output, Some(OutputFormat::default()));
+        assert_eq!(settings.helper, None);
+        assert_eq!(
+            settings.usernames,
+            usernames(&[("docker.com", "profile"), ("github.com", "octocat")])
+        );
+    }
+
+    #[test]
+    fn test_legacy_session_moves_to_default_profile() {
+        let path = Path::new("/home/user/.ipass/config.json");
+        let config = ConfigFile::parse_legacy(
+            path,
+            r#"{"username":"u","shared_key":"a2V5","browser":"Chrome"}"#,
+        )
+        .unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.profiles.len(), 1);
+        let profile = &config.profiles[DEFAULT_PROFILE];
+        assert_eq!(profile.browser, Browser::Chrome);
+        let session = profile.session.as_ref().unwrap();
+        assert_eq!(session.username, "u");
+        assert_eq!(session.shared_key.as_str(), "a2V5");
+        assert_eq!(session.protection, KeyProtection::None);
+    }
+
+    #[test]
+    fn test_legacy_config_without_session_has_none() {
+        let path = Path::new("/home/user/.ipass/config.json");
+        let config = ConfigFile::parse_legacy(path, r#"{"username":"","shared_key":""}"#).unwrap();
+        let profile = &config.profiles[DEFAULT_PROFILE];
+        assert!(profile.session.is_none());
+        assert_eq!(profile.browser, Browser::default());
+    }
+
+    #[test]
+    fn test_legacy_path_with_profiles_is_read_as_is() {
+        let path = Path::new("/home/user/.ipass/config.json");
+        let config =
+            ConfigFile::parse_legacy(path, r#"{"version":1,"profiles":{"work":{"port":27390}}}"#)
+                .unwrap();
+        assert_eq!(config.profiles.keys().collect::<Vec<_>>(), ["work"]);
+        assert_eq!(config.profiles["work"].settings.port, Some(27390));
+        assert!(ConfigFile::parse_legacy(path, "{").is_err());
+    }
+
+    #[test]
+    fn test_sessions_are_cleared_per_daemon() {
+        let mut config = ConfigFile::default();
+        let session = PassConfig::new("u".to_owned(), "a2V5".to_owned().into());
+        for (name, port, socket) in [
+            ("default", None, None),
+            ("other", Some(27390), None),
+            ("unix", None, Some("/run/ipass.sock")),
+        ] {
+            let profile = config.profile_mut(name);
+            profile.settings.port = port;
+            profile.settings.socket = socket.map(PathBuf::from);
+            profile.session = Some(session.clone());
+        }
+
+        config.clear_sessions(&DaemonAddr::Udp {
+            host: "127.0.0.1".to_owned(),
+            port: crate::DEFAULT_PORT,
+        });
+        config.clear_sessions(&DaemonAddr::Unix("/run/ipass.sock".into()));
+        let remaining = config
+            .profiles
+            .iter()
+            .filter(|(_, profile)| profile.session.is_some())
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(remaining, ["other"]);
+    }
+
+    #[test]
+    fn test_write_private_leaves_the_directory_alone() {
+        let dir = temp_dir("write-private");
+        std::fs::set_permissions(&dir, Permissions::from_mode(0o755)).unwrap();
+        let path = dir.join("config.json");
+        write_with_mode(&path, 0o644);
+        write_private(&path, "{\"version\":1}").unwrap();
+
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&dir), 0o755);
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(
+            read_private(&path).unwrap().as_deref(),
+            Some("{\"version\":1}")
+        );
+    }
+
+    #[test]
+    fn test_write_private_replaces_the_file_at_once() {
+        let dir = temp_dir("write-private-replace");
+        let path = dir.join("config.json");
+        write_private(&path, "{\"version\":1}").unwrap();
+        write_private(&path, "{\"version\":2}").unwrap();
+        assert_eq!(
+            read_private(&path).unwrap().as_deref(),
+            Some("{\"version\":2}")
+        );
+        let files = std::fs::read_dir(&dir).unwrap().count();
+        assert_eq!(files, 1, "temporary files are left behind");
+    }
+
+    #[test]
+    fn test_failed_write_private_keeps_the_old_content() {
+        let dir = temp_dir("write-private-failed");
+        // Renaming a file over a non-empty directory fails after writing it.
+        let path = dir.join("config.json");
+        std::fs::create_dir(&path).unwrap();
+        std::fs::write(path.join("keep"), "old").unwrap();
+        assert!(write_private(&path, "{\"version\":2}").is_err());
+        assert_eq!(std::fs::read_to_string(path.join("keep")).unwrap(), "old");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_private_dir_is_created_for_ipass() {
+        let dir = temp_dir("private-dir").join("ipass");
+        create_private_dir(&dir).unwrap();
+        let mode = std::fs::metadata(&dir).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o700);
+    }
+
+    #[test]
+    fn test_shared_directory_is_only_warned_about() {
+        let dir = temp_dir("shared-dir");
+        let path = dir.join("config.json");
+        write_with_mode(&path, 0o600);
+        std::fs::set_permissions(&dir, Permissions::from_mode(0o755)).unwrap();
+        assert!(check_permissions(&path).is_ok());
     }
 }
@@ -1,18 +1,27 @@
-use log::info;
-use std::io::{Read, Write};
+use log::{info, warn};
+use std::fs::Permissions;
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::os::unix::fs::PermissionsExt;
 #[allow(deprecated)]
 use std::{
-    path::Path,
+    path::{Path, PathBuf},
     process::{Command, Stdio},
 };
-use tokio::{io, net::UdpSocket};
+use tokio::net::{UdpSocket, UnixDatagram};
 
 use serde::{Deserialize, Serialize};
 use tokio::select;
 
 use core::str;
 
-use crate::{config::PassConfig, StartArgs};
+use ipass::{
+    client::DaemonAddr,
+    config::{ConfigFile, Profile},
+    error::{IpassError, Result},
+};
+
+use crate::StartArgs;
 
 #[derive(Debug, Deserialize, Serialize)]
 struct PasswordManager {
@@ -24,24 +33,128 @@ struct PasswordManager {
     allowed_extensions: Option<Vec<String>>,
 }
 
-impl Default for PasswordManager {
-    fn default() -> Self {
+impl PasswordManager {
+    /// Reads the native messaging manifest the browsers use to find the helper.
+    fn find() -> Result<Self> {
         let path = [
             "/Library/Application Support/Mozilla/NativeMessagingHosts/com.apple.passwordmanager.json",
             "/Library/Google/Chrome/NativeMessagingHosts/com.apple.passwordmanager.json",
         ]
         .iter()
         .find(|path| Path::new(path).exists())
-        .expect("no passwordmanager config file");
-        let content = std::fs::read_to_string(path).expect("read passwordmanager config file");
-        serde_json::from_str(&content).expect("parse passwordmanager config file")
+        .ok_or_else(|| {
+            IpassError::NotFound(format!(
+                "password manager helper not found, please pass `--helper` or run `{} config set helper <path>`",
+                crate::my_cli()
+            ))
+        })?;
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+}
+
+/// Socket the daemon receives requests on.
+enum Listener {
+    Udp(UdpSocket),
+    /// Bound to the path, which is removed when dropped
+    Unix(UnixDatagram, PathBuf),
+}
+
+/// Where to send the reply to a request.
+enum Peer {
+    Udp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl Listener {
+    async fn bind(addr: &DaemonAddr) -> Result<Self> {
+        match addr {
+            DaemonAddr::Udp { port, .. } => {
+                let socket = UdpSocket::bind(format!("127.0.0.1:{}", port)).await?;
+                info!(
+                    "Daemon is listening on port: {}",
+                    socket.local_addr()?.port()
+                );
+                Ok(Self::Udp(socket))
+            }
+            DaemonAddr::Unix(path) => {
+                let socket = match UnixDatagram::bind(path) {
+                    // Left behind by a daemon that was killed, nothing listens on it.
+                    Err(err) if err.kind() == io::ErrorKind::AddrInUse && is_stale(path) => {
+                        std::fs::remove_file(path)?;
+                        UnixDatagram::bind(path)?
+                    }
+                    result => result?,
+                };
+                let socket = Self::Unix(socket, path.clone());
+                // Unlike the UDP port, the socket is kept to the daemon's user.
+                std::fs::set_permissions(path, Permissions::from_mode(0o600))?;
+                info!("Daemon is listening on socket: {}", path.display());
+                Ok(socket)
+            }
+        }
+    }
+
+    /// The address clients reach the daemon at, with the port actually bound.
+    fn local_addr(&self) -> Result<DaemonAddr> {
+        match self {
+            Self::Udp(socket) => Ok(DaemonAddr::Udp {
+                host: "127.0.0.1".to_owned(),
+                port: socket.local_addr()?.port(),
+            }),
+            Self::Unix(_, path) => Ok(DaemonAddr::Unix(path.clone())),
+        }
+    }
+
+    /// Receives a request, with no peer if it came from an unbound Unix socket
+    /// that can't be replied to.
+    async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, Option<Peer>)> {
+        match self {
+            Self::Udp(socket) => {
+                let (len, addr) = socket.recv_from(buf).await?;
+                Ok((len, Some(Peer::Udp(addr))))
+            }
+            Self::Unix(socket, _) => {
+                let (len, addr) = socket.recv_from(buf).await?;
+                Ok((
+                    len,
+                    addr.as_pathname().map(|path| Peer::Unix(path.to_owned())),
+                ))
+            }
+        }
+    }
+
+    async fn send_to(&self, buf: &[u8], peer: &Peer) -> Result<()> {
+        match (self, peer) {
+            (Self::Udp(socket), Peer::Udp(addr)) => socket.send_to(buf, addr).await?,
+            (Self::Unix(socket, _), Peer::Unix(path)) => socket.send_to(buf, path).await?,
+            _ => unreachable!("peers come from the same listener"),
+        };
+        Ok(())
+    }
+}
+
+fn is_stale(path: &Path) -> bool {
+    UnixDatagram::unbound()
+        .and_then(|socket| socket.connect(path))
+        .is_err_and(|err| err.kind() == io::ErrorKind::ConnectionRefused)
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Self::Unix(_, path) = self {
+            let _ = std::fs::remove_file(path);
+        }
     }
 }
 
-pub async fn start(args: StartArgs) -> io::Result<()> {
-    let password_manager = PasswordManager::default();
+pub async fn start(args: StartArgs, profile: &Profile) -> Result<()> {
+    let helper = match args.helper.or_else(|| profile.settings.helper.clone()) {
+        Some(helper) => helper,
+        None => PasswordManager::find()?.path.into(),
+    };
 
-    let mut pm_process = Command::new(&password_manager.path)
+    let mut pm_process = Command::new(&helper)
         .arg(".")
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
@@ -50,20 +163,29 @@ pub async fn start(args: StartArgs) -> io::Result<()> {
     let stdin = pm_process.stdin.as_mut().expect("get pm process stdin");
     let stdout = pm_process.stdout.as_mut().expect("get pm process stdout");
 
-    let socket = UdpSocket::bind(format!("127.0.0.1:{}", args.port)).await?;
-    let port = socket.local_addr()?.port();
-    info!("Daemon is listening on port: {}", port);
+    let addr = match args.socket {
+        Some(path) => DaemonAddr::Unix(path),
+        None => profile.settings.daemon_addr(args.port),
+    };
+    let listener = Listener::bind(&addr).await?;
+    let addr = listener.local_addr()?;
 
     let mut buf = [0; 4096];
 
     loop {
         select! {
             _ = tokio::signal::ctrl_c() => {
-                PassConfig::new("".to_owned(), "".to_owned()).save();
+                let mut config = ConfigFile::load()?;
+                config.clear_sessions(&addr);
+                config.save()?;
                 break;
             }
-            result = socket.recv_from(&mut buf[4..]) => {
-                let (len, addr) = result?;
+            result = listener.recv_from(&mut buf[4..]) => {
+                let (len, peer) = result?;
+                let Some(peer) = peer else {
+                    warn!("Ignoring request from an unbound socket, which can't receive replies");
+                    continue;
+                };
 
                 buf[..4].copy_from_slice(&(len as u32).to_le_bytes());
                 stdin.write_all(&buf[..len + 4])?;
@@ -72,7 +194,7 @@ pub async fn start(args: StartArgs) -> io::Result<()> {
                 let len = u32::from_le_bytes(buf[..4].try_into().unwrap()) as usize;
                 stdout.read_exact(&mut buf[4..len + 4])?;
 
-                socket.send_to(&buf[4..len + 4], addr).await?;
+                listener.send_to(&buf[4..len + 4], &peer).await?;
             }
         }
     }
@@ -0,0 +1,69 @@
+use thiserror::Error;
+use tokio::io;
+
+use crate::client::DaemonAddr;
+
+pub type Result<T> = std::result::Result<T, IpassError>;
+
+/// Failures of the CLI, each mapped to its own process exit code so scripts
+/// can tell them apart, see [`IpassError::exit_code`]. Messages don't say which
+/// command fixes them, as that depends on how the library is used.
+#[derive(Debug, Error)]
+pub enum IpassError {
+    #[error("{0}")]
+    InvalidInput(String),
+    #[error("daemon is not reachable at {0}")]
+    DaemonUnreachable(DaemonAddr),
+    #[error("session is not authenticated")]
+    NotAuthenticated,
+    #[error("session was rejected by the password manager helper")]
+    SessionRejected,
+    #[error("wrong PIN")]
+    WrongPin,
+    #[error("password manager helper failed with error code {0}")]
+    Helper(i64),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    PermissionDenied(String),
+    #[error("invalid reply from the password manager helper: {0}")]
+    Protocol(String),
+    #[error("{0}")]
+    NoMatch(String),
+    #[error("{0}")]
+    Ambiguous(String),
+    #[error("cancelled")]
+    Cancelled,
+    #[error("{0}")]
+    Exists(String),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+impl IpassError {
+    /// The process exit code, part of the CLI interface documented in the README.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::Io(_) | Self::Json(_) => 1,
+            Self::InvalidInput(_) => 2,
+            Self::DaemonUnreachable(_) => 3,
+            Self::NotAuthenticated | Self::SessionRejected => 4,
+            Self::WrongPin => 5,
+            Self::Helper(_) => 6,
+            Self::NotFound(_) => 7,
+            Self::PermissionDenied(_) => 8,
+            Self::Protocol(_) => 9,
+            Self::NoMatch(_) => 10,
+            Self::Ambiguous(_) => 11,
+            Self::Cancelled => 12,
+            Self::Exists(_) => 13,
+        }
+    }
+
+    /// Whether authenticating again may fix this error.
+    pub fn needs_auth(&self) -> bool {
+        matches!(self, Self::NotAuthenticated | Self::SessionRejected)
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::BTreeSet;
+
+use clap::ValueEnum;
+use rand::seq::{IndexedRandom, SliceRandom};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::error::{IpassError, Result};
+use crate::passwordrules::PasswordRules;
+use crate::secret::SecretString;
+
+/// Words of diceware passphrases, the BIP39 English wordlist (CC0), whose
+/// words are unambiguous by their first four letters.
+const WORDLIST: &str = include_str!("wordlist.txt");
+
+const LOWER: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPER: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &str = "0123456789";
+/// ASCII punctuation accepted by most sites, without quotes, backslash and space.
+const SYMBOLS: &str = "!#$%&()*+,-./:;<=>?@[]^_{|}~";
+
+/// Characters a generated password may contain, each of the chosen ones at least once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum CharClass {
+    Lower,
+    Upper,
+    Digits,
+    Symbols,
+}
+
+impl CharClass {
+    pub const ALL: [CharClass; 4] = [Self::Lower, Self::Upper, Self::Digits, Self::Symbols];
+
+    pub fn chars(self) -> &'static str {
+        match self {
+            Self::Lower => LOWER,
+            Self::Upper => UPPER,
+            Self::Digits => DIGITS,
+            Self::Symbols => SYMBOLS,
+        }
+    }
+}
+
+/// A strong password like Safari suggests, `xxxxxx-xxxxxx-xxxxxx` of lowercase
+/// letters with one of them uppercase and one a digit.
+pub fn apple() -> SecretString {
+    let mut rng = rand::rng();
+    let mut chars = Zeroizing::new(
+        (0..18)
+            .map(|_| random_char(&mut rng, LOWER))
+            .collect::<Vec<_>>(),
+    );
+    let positions = rand::seq::index::sample(&mut rng, chars.len(), 2);
+    chars[positions.index(0)] = random_char(&mut rng, UPPER);
+    chars[positions.index(1)] = random_char(&mut rng, DIGITS);
+
+    SecretString::new(
+        chars
+            .chunks(6)
+            .map(|group| group.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join("-"),
+    )
+}
+
+/// A password of `length` characters from `classes`, with at least one of each.
+pub fn characters(length: usize, classes: &[CharClass]) -> Result<SecretString> {
+    let mut classes = classes.to_vec();
+    classes.sort_by_key(|class| *class as u8);
+    classes.dedup();
+    if classes.is_empty() {
+        return Err(IpassError::InvalidInput(
+            "at least one character class is needed".to_owned(),
+        ));
+    }
+    if length < classes.len() {
+        return Err(IpassError::InvalidInput(format!(
+            "a password with {} character classes needs at least {} characters",
+            classes.len(),
+            classes.len()
+        )));
+    }
+
+    let mut rng = rand::rng();
+    let all = classes
+        .iter()
+        .map(|class| class.chars())
+        .collect::<String>();
+    let mut chars = Zeroizing::new(Vec::with_capacity(length));
+    for class in &classes {
+        chars.push(random_char(&mut rng, class.chars()));
+    }
+    while chars.len() < length {
+        chars.push(random_char(&mut rng, &all));
+    }
+    chars.shuffle(&mut rng);
+
+    Ok(SecretString::new(chars.iter().collect()))
+}
+
+/// A password satisfying `rules`, `length` characters long if they allow it.
+/// Spaces are never used, even where the rules allow them.
+pub fn with_rules(rules: &PasswordRules, length: usize) -> Result<SecretString> {
+    let max_length = rules.max_length.unwrap_or(usize::MAX);
+    let min_length = rules.min_length.unwrap_or(0);
+    if min_length > max_length {
+        return Err(IpassError::InvalidInput(format!(
+            "password rules require at least {} but at most {} characters",
+            min_length, max_length
+        )));
+    }
+    let length = length.clamp(min_length, max_length);
+
+    let usable = |set: &BTreeSet<char>| {
+        set.iter()
+            .copied()
+            .filter(|c| *c != ' ')
+            .collect::<Vec<_>>()
+    };
+    let allowed = usable(&rules.allowed_chars());
+    let required = rules.required.iter().map(usable).collect::<Vec<_>>();
+    if allowed.is_empty() || required.iter().any(Vec::is_empty) {
+        return Err(IpassError::InvalidInput(
+            "password rules allow no characters to generate a password from".to_owned(),
+        ));
+    }
+    if required.len() > length {
+        return Err(IpassError::InvalidInput(format!(
+            "password rules require {} character classes in {} characters",
+            required.len(),
+            length
+        )));
+    }
+
+    let mut rng = rand::rng();
+    // Shuffling rarely breaks `max-consecutive`, so retry a few times.
+    for _ in 0..100 {
+        let mut chars = Zeroizing::new(Vec::<char>::with_capacity(length));
+        for set in &required {
+            chars.extend(set.choose(&mut rng));
+        }
+        while chars.len() < length {
+            chars.extend(allowed.choose(&mut rng));
+        }
+        chars.shuffle(&mut rng);
+
+        let password = SecretString::new(chars.iter().collect());
+        if rules.check(&password) {
+            return Ok(password);
+        }
+    }
+    Err(IpassError::InvalidInput(
+        "failed to generate a password satisfying the password rules".to_owned(),
+    ))
+}
+
+/// A passphrase of `words` random words from the bundled wordlist.
+pub fn diceware(words: usize, separator: &str) -> Result<SecretString> {
+    if words == 0 {
+        return Err(IpassError::InvalidInput(
+            "a passphrase needs at least one word".to_owned(),
+        ));
+    }
+    let wordlist = WORDLIST.lines().collect::<Vec<_>>();
+    let mut rng = rand::rng();
+    let phrase = (0..words)
+        .map(|_| *wordlist.choose(&mut rng).unwrap_or(&""))
+        .collect::<Vec<_>>()
+        .join(separator);
+    Ok(SecretString::new(phrase))
+}
+
+fn random_char(rng: &mut impl Rng, chars: &str) -> char {
+    let chars = chars.as_bytes();
+    chars[rng.random_range(..chars.len())] as char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apple() {
+        let password = apple();
+        let groups = password.split('-').collect::<Vec<_>>();
+        assert_eq!(groups.len(), 3);
+        assert!(groups.iter().all(|group| group.len() == 6));
+        assert_eq!(password.chars().filter(char::is_ascii_uppercase).count(), 1);
+        assert_eq!(password.chars().filter(char::is_ascii_digit).count(), 1);
+        assert_eq!(
+            password.chars().filter(char::is_ascii_lowercase).count(),
+            16
+        );
+    }
+
+    #[test]
+    fn test_characters_has_every_class() {
+        for _ in 0..100 {
+            let password = characters(4, &CharClass::ALL).unwrap();
+            assert_eq!(password.len(), 4);
+            for class in CharClass::ALL {
+                assert!(password.chars().any(|c| class.chars().contains(c)));
+            }
+        }
+        let password = characters(32, &[CharClass::Digits]).unwrap();
+        assert!(password.chars().all(|c| c.is_ascii_digit()));
+        assert!(characters(3, &CharClass::ALL).is_err());
+        assert!(characters(8, &[]).is_err());
+    }
+
+    #[test]
+    fn test_with_rules() {
+        let rules = PasswordRules::parse(
+            "minlength: 8; maxlength: 12; required: lower; required: upper; required: digit; \
+             required: [!#]; max-consecutive: 1;",
+        )
+        .unwrap();
+        for _ in 0..100 {
+            let password = with_rules(&rules, 20).unwrap();
+            assert_eq!(password.len(), 12);
+            assert!(rules.check(&password));
+        }
+        let rules = PasswordRules::parse("minlength: 30; allowed: ascii-printable").unwrap();
+        let password = with_rules(&rules, 20).unwrap();
+        assert_eq!(password.len(), 30);
+        assert!(!password.contains(' '));
+
+        let rules = PasswordRules::parse("required: [ ]").unwrap();
+        assert!(with_rules(&rules, 20).is_err());
+        let rules = PasswordRules::parse("minlength: 10; maxlength: 8").unwrap();
+        assert!(with_rules(&rules, 20).is_err());
+    }
+
+    #[test]
+    fn test_diceware() {
+        assert_eq!(WORDLIST.lines().count(), 2048);
+        let phrase = diceware(7, " ").unwrap();
+        let words = phrase.split(' ').collect::<Vec<_>>();
+        assert_eq!(words.len(), 7);
+        assert!(words
+            .iter()
+            .all(|word| WORDLIST.lines().any(|w| w == *word)));
+        assert!(diceware(0, "-").is_err());
+    }
+}
@@ -0,0 +1,247 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use aead::{Aead, KeyInit};
+use aes_gcm::Aes256Gcm;
+use base64::prelude::*;
+use clap::ValueEnum;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::io;
+use zeroize::Zeroizing;
+
+use crate::error::{IpassError, Result};
+use crate::secret::{SecretBytes, SecretString};
+
+/// Keychain service the wrapping keys are stored under, one account per profile.
+const KEYCHAIN_SERVICE: &str = "ipass";
+const PASSPHRASE_ENV: &str = "IPASS_PASSPHRASE";
+const PBKDF2_ROUNDS: u32 = 600_000;
+const NONCE_LEN: usize = 12;
+
+/// How the session key is protected in the config file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyProtection {
+    /// Store the session key as is
+    #[default]
+    None,
+    /// Wrap the session key with a random key kept in the macOS keychain
+    Keychain,
+    /// Wrap the session key with a key derived from a passphrase
+    Passphrase,
+}
+
+/// Encrypts `key`, returning the base64 encoded ciphertext and, for
+/// passphrase protection, the base64 encoded KDF salt.
+pub fn wrap(
+    key: &[u8],
+    protection: KeyProtection,
+    profile: &str,
+) -> Result<(SecretString, Option<String>)> {
+    let (wrapping_key, salt) = match protection {
+        KeyProtection::None => return Ok((BASE64_STANDARD.encode(key).into(), None)),
+        KeyProtection::Keychain => {
+            let wrapping_key = SecretBytes::random(32);
+            keychain_store(&wrapping_key, profile)?;
+            (wrapping_key, None)
+        }
+        KeyProtection::Passphrase => {
+            let mut salt = [0u8; 16];
+            rand::rng().fill_bytes(&mut salt);
+            let phrase = passphrase("Enter a passphrase to protect the session key: ")?;
+            if phrase != passphrase("Confirm passphrase: ")? {
+                return Err(IpassError::InvalidInput(
+                    "passphrases do not match".to_owned(),
+                ));
+            }
+            (
+                derive_key(&phrase, &salt, PBKDF2_ROUNDS),
+                Some(BASE64_STANDARD.encode(salt)),
+            )
+        }
+    };
+
+    Ok((seal(key, &wrapping_key)?, salt))
+}
+
+/// Reverses [`wrap`], prompting for the passphrase if needed.
+pub fn unwrap(
+    wrapped: &str,
+    protection: KeyProtection,
+    salt: Option<&str>,
+    profile: &str,
+) -> Result<SecretBytes> {
+    let wrapped = SecretBytes::new(
+        BASE64_STANDARD
+            .decode(wrapped)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+    );
+    let wrapping_key = match protection {
+        KeyProtection::None => return Ok(wrapped),
+        KeyProtection::Keychain => keychain_load(profile)?,
+        KeyProtection::Passphrase => {
+            let salt = salt
+                .map(|salt| BASE64_STANDARD.decode(salt))
+                .transpose()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "missing passphrase salt")
+                })?;
+            derive_key(&passphrase("Enter passphrase: ")?, &salt, PBKDF2_ROUNDS)
+        }
+    };
+
+    open(&wrapped, &wrapping_key)
+}
+
+/// Encrypts `key` with `wrapping_key`, returning the base64 encoded nonce and ciphertext.
+fn seal(key: &[u8], wrapping_key: &[u8]) -> Result<SecretString> {
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::rng().fill_bytes(&mut nonce);
+    let cipher = Aes256Gcm::new_from_slice(wrapping_key)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid wrapping key"))?;
+    let encrypted = cipher
+        .encrypt(&nonce.into(), key)
+        .map_err(|_| io::Error::other("failed to encrypt session key"))?;
+
+    Ok(BASE64_STANDARD
+        .encode([&nonce[..], encrypted.as_slice()].concat())
+        .into())
+}
+
+/// Reverses [`seal`] on the decoded nonce and ciphertext.
+fn open(wrapped: &[u8], wrapping_key: &[u8]) -> Result<SecretBytes> {
+    if wrapped.len() < NONCE_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "wrapped session key is truncated",
+        )
+        .into());
+    }
+    let (nonce, encrypted) = wrapped.split_at(NONCE_LEN);
+    let cipher = Aes256Gcm::new_from_slice(wrapping_key)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid wrapping key"))?;
+    cipher
+        .decrypt(
+            nonce.try_into().expect("nonce length checked above"),
+            encrypted,
+        )
+        .map(SecretBytes::new)
+        .map_err(|_| {
+            IpassError::PermissionDenied(
+                "failed to unlock session key: wrong passphrase or keychain item".to_owned(),
+            )
+        })
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], rounds: u32) -> SecretBytes {
+    let mut key = SecretBytes::new(vec![0; 32]);
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, rounds, &mut key);
+    key
+}
+
+fn passphrase(prompt: &str) -> Result<SecretString> {
+    match std::env::var(PASSPHRASE_ENV) {
+        Ok(passphrase) => Ok(passphrase.into()),
+        Err(_) => Ok(rpassword::prompt_password(prompt).map(SecretString::new)?),
+    }
+}
+
+fn keychain_store(key: &[u8], profile: &str) -> Result<()> {
+    // Commands are fed through stdin so the key never shows up in the process list.
+    let mut security = Command::new("security")
+        .arg("-i")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    let command = Zeroizing::new(format!(
+        "add-generic-password -U -a {} -s {} -w {}\n",
+        profile,
+        KEYCHAIN_SERVICE,
+        Zeroizing::new(BASE64_STANDARD.encode(key)).as_str()
+    ));
+    security
+        .stdin
+        .take()
+        .expect("get security stdin")
+        .write_all(command.as_bytes())?;
+    if !security.wait()?.success() {
+        return Err(io::Error::other("failed to store key in keychain").into());
+    }
+    Ok(())
+}
+
+fn keychain_load(profile: &str) -> Result<SecretBytes> {
+    let output = Command::new("security")
+        .args([
+            "find-generic-password",
+            "-a",
+            profile,
+            "-s",
+            KEYCHAIN_SERVICE,
+            "-w",
+        ])
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(IpassError::NotFound(
+            "session key not found in keychain".to_owned(),
+        ));
+    }
+    let stdout = SecretBytes::new(output.stdout);
+    BASE64_STANDARD
+        .decode(stdout.trim_ascii())
+        .map(SecretBytes::new)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SALT: [u8; 16] = [7; 16];
+    /// Far fewer rounds than [`PBKDF2_ROUNDS`], which take long in debug builds.
+    const ROUNDS: u32 = 1_000;
+
+    #[test]
+    fn test_passphrase_round_trip() {
+        let key = SecretBytes::random(32);
+        let wrapped = seal(&key, &derive_key("correct horse", &SALT, ROUNDS)).unwrap();
+        let wrapped = BASE64_STANDARD.decode(wrapped.as_str()).unwrap();
+        let unwrapped = open(&wrapped, &derive_key("correct horse", &SALT, ROUNDS)).unwrap();
+        assert_eq!(*unwrapped, *key);
+    }
+
+    #[test]
+    fn test_wrong_passphrase_is_denied() {
+        let wrapped = seal(b"session key", &derive_key("correct horse", &SALT, ROUNDS)).unwrap();
+        let wrapped = BASE64_STANDARD.decode(wrapped.as_str()).unwrap();
+        let err = open(&wrapped, &derive_key("battery staple", &SALT, ROUNDS)).unwrap_err();
+        assert!(matches!(err, IpassError::PermissionDenied(_)));
+        let err = open(
+            &wrapped[..NONCE_LEN - 1],
+            &derive_key("correct horse", &SALT, ROUNDS),
+        )
+        .unwrap_err();
+        assert!(matches!(err, IpassError::Io(_)));
+    }
+
+    #[test]
+    fn test_derive_key_depends_on_salt() {
+        let key = derive_key("correct horse", &SALT, ROUNDS);
+        assert_eq!(key.len(), 32);
+        assert_eq!(*key, *derive_key("correct horse", &SALT, ROUNDS));
+        assert_ne!(*key, *derive_key("correct horse", &[8; 16], ROUNDS));
+    }
+
+    #[test]
+    fn test_no_protection_is_plain_base64() {
+        let (wrapped, salt) = wrap(b"session key", KeyProtection::None, "default").unwrap();
+        assert_eq!(wrapped.as_str(), BASE64_STANDARD.encode(b"session key"));
+        assert_eq!(salt, None);
+        let key = unwrap(&wrapped, KeyProtection::None, None, "default").unwrap();
+        assert_eq!(&*key, b"session key");
+    }
+}
@@ -0,0 +1,20 @@
+//! Access to Apple Passwords through the password manager helper of macOS.
+//!
+//! The helper is reached through a daemon started by `ipass start`, see
+//! [`client::Client`] for the typed API the `ipass` CLI is built on.
+
+pub mod client;
+pub mod config;
+pub mod error;
+pub mod generate;
+pub mod keywrap;
+pub mod output;
+pub mod passwordrules;
+pub mod paths;
+pub mod secret;
+pub mod srp;
+pub mod types;
+pub mod util;
+
+/// Port the daemon listens on unless configured otherwise.
+pub const DEFAULT_PORT: u16 = 27389;
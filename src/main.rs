@@ -1,18 +1,25 @@
 pub mod auth;
-pub mod config;
+pub mod capabilities;
+pub mod clip;
 pub mod daemon;
 pub mod otp;
+pub mod profile;
 pub mod pw;
-pub mod srp;
-pub mod types;
-pub mod util;
+pub mod settings;
 
 use clap::{Args, Parser, Subcommand};
 use clap_verbosity_flag::{InfoLevel, Verbosity};
 
-use std::io;
-
-const DEFAULT_PORT: u16 = 27389;
+use ipass::config::{ConfigFile, Profile, DEFAULT_PROFILE};
+use ipass::error::{IpassError, Result};
+use ipass::output::{Field, OutputFormat};
+use ipass::secret::SecretString;
+use ipass::{generate, keywrap, paths, secret, types};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -21,29 +28,68 @@ struct PassArgs {
     #[command(flatten)]
     verbose: Verbosity<InfoLevel>,
 
+    /// Config file to use [default: $XDG_CONFIG_HOME/ipass/config.json]
+    #[arg(long, global = true, env = "IPASS_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Profile to use, see `profile list`
+    #[arg(long, global = true, env = "IPASS_PROFILE", default_value = DEFAULT_PROFILE, value_parser = profile::parse_name)]
+    profile: String,
+
+    /// Seconds to wait for the daemon to reply [default: 5]
+    #[arg(long, global = true, env = "IPASS_TIMEOUT")]
+    timeout: Option<u64>,
+
+    /// Format of the results of `pw` and `otp` commands [default: json]
+    #[arg(long, global = true, env = "IPASS_OUTPUT", value_enum)]
+    output: Option<OutputFormat>,
+
+    /// Lock memory holding secrets so it is never swapped to disk
+    #[arg(long, global = true, env = "IPASS_MLOCK")]
+    mlock: bool,
+
     #[command(subcommand)]
     cmd: Commands,
 }
 
 #[derive(Args, Debug, Clone)]
 pub struct StartArgs {
-    /// Port to listen on
-    #[arg(long, default_value_t = DEFAULT_PORT)]
-    port: u16,
+    /// Port to listen on, defaults to the profile's port
+    #[arg(long, env = "IPASS_PORT")]
+    port: Option<u16>,
+    /// Unix socket to listen on instead of a port, defaults to the profile's socket
+    #[arg(long, env = "IPASS_SOCKET", conflicts_with = "port")]
+    socket: Option<PathBuf>,
+    /// Password manager helper to run, defaults to the `helper` setting
+    #[arg(long, env = "IPASS_HELPER")]
+    helper: Option<PathBuf>,
 }
 
 #[derive(Args, Debug, Clone)]
 pub struct AuthArgs {
-    /// Port to connect to
-    #[arg(long, default_value_t = DEFAULT_PORT)]
-    port: u16,
+    /// Port to connect to, defaults to the profile's port
+    #[arg(long, env = "IPASS_PORT")]
+    port: Option<u16>,
+    /// How to protect the session key stored in the config file
+    #[arg(long, value_enum, default_value_t = keywrap::KeyProtection::None)]
+    protect: keywrap::KeyProtection,
+    /// Host browser to present to the password manager helper, defaults to the profile's browser
+    #[arg(long, value_enum)]
+    browser: Option<types::Browser>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CapabilitiesArgs {
+    /// Port to connect to, defaults to the profile's port
+    #[arg(long, env = "IPASS_PORT")]
+    port: Option<u16>,
 }
 
 #[derive(Args, Debug, Clone)]
 pub struct ListArgs {
-    /// Port to connect to
-    #[arg(long, default_value_t = DEFAULT_PORT)]
-    port: u16,
+    /// Port to connect to, defaults to the profile's port
+    #[arg(long, env = "IPASS_PORT")]
+    port: Option<u16>,
     /// Website url to list account for
     #[arg()]
     url: String,
@@ -51,26 +97,65 @@ pub struct ListArgs {
 
 #[derive(Args, Debug, Clone)]
 pub struct GetArgs {
-    /// Port to connect to
-    #[arg(long, default_value_t = DEFAULT_PORT)]
-    port: u16,
+    /// Port to connect to, defaults to the profile's port
+    #[arg(long, env = "IPASS_PORT")]
+    port: Option<u16>,
     /// Website url to get password for
     #[arg()]
     url: String,
-    /// User name to get password for
-    username: String,
+    /// User name to get password for, defaults to the `usernames` setting of the domain
+    username: Option<String>,
+    /// Print only this field of the account, which must be the only match
+    #[arg(long, value_enum)]
+    field: Option<Field>,
+    /// Print the field without a trailing newline, the password unless `--field` is given
+    #[arg(long)]
+    raw: bool,
+    /// Copy the field to the clipboard instead of printing it, the password unless `--field` is given
+    #[arg(long, conflicts_with = "raw")]
+    clip: bool,
 }
 
 #[derive(Args, Debug, Clone)]
 pub struct OtpArgs {
-    /// Port to connect to
-    #[arg(long, default_value_t = DEFAULT_PORT)]
-    port: u16,
+    /// Port to connect to, defaults to the profile's port
+    #[arg(long, env = "IPASS_PORT")]
+    port: Option<u16>,
     /// Website url to get one time password for
     #[arg()]
     url: String,
-    /// User name to get one time password for
-    username: String,
+    /// User name to get one time password for, defaults to the `usernames` setting
+    /// of the domain. Codes of other users are left out, codes of no user are kept
+    username: Option<String>,
+    /// Only codes from this source, as reported in the `source` field of the entries
+    #[arg(long)]
+    source: Option<String>,
+    /// Only the most recent code, taken to be the first one the helper reports
+    /// as codes carry no time
+    #[arg(long)]
+    latest: bool,
+    /// Print only the code, which must be the only match
+    #[arg(long)]
+    code_only: bool,
+    /// Print only the code, without a trailing newline
+    #[arg(long)]
+    raw: bool,
+    /// Copy only the code to the clipboard instead of printing it
+    #[arg(long, conflicts_with = "raw")]
+    clip: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ClipClearArgs {
+    /// Seconds to wait before clearing
+    #[arg(long)]
+    after: u64,
+    /// Shell command copying its input to the clipboard, OSC 52 if not given
+    #[arg(long)]
+    copy_command: Option<String>,
+    /// Shell command printing the clipboard, to check it still holds the copied value
+    #[arg(long)]
+    paste_command: Option<String>,
 }
 
 #[derive(Args, Debug, Clone)]
@@ -82,16 +167,147 @@ pub struct InstallArgs {
 
 #[derive(Args, Debug, Clone)]
 pub struct SaveArgs {
-    /// Port to connect to
-    #[arg(long, default_value_t = DEFAULT_PORT)]
-    port: u16,
+    /// Port to connect to, defaults to the profile's port
+    #[arg(long, env = "IPASS_PORT")]
+    port: Option<u16>,
     /// Website url to save password for
     #[arg()]
     url: String,
     /// User name to save password for
     username: String,
-    /// Password to save
-    password: String,
+    /// Password to save, only with `--insecure-password-arg` as it ends up in the
+    /// shell history and process list. Prompted for by default
+    #[arg(requires = "insecure_password_arg", conflicts_with_all = ["password_stdin", "password_file"])]
+    password: Option<String>,
+    /// Allow passing the password as an argument
+    #[arg(long, requires = "password")]
+    insecure_password_arg: bool,
+    #[command(flatten)]
+    input: PasswordInput,
+    /// Show whether the account would be created or replaced, without saving
+    #[arg(long)]
+    dry_run: bool,
+    /// Fail instead of replacing the password of an existing account
+    #[arg(long)]
+    no_overwrite: bool,
+    /// Replace the password of an existing account without asking
+    #[arg(long, short, conflicts_with = "no_overwrite")]
+    yes: bool,
+}
+
+/// Where to read a password from instead of prompting for it.
+#[derive(Args, Debug, Clone)]
+pub struct PasswordInput {
+    /// Read the password from the standard input
+    #[arg(long, conflicts_with = "password_file")]
+    password_stdin: bool,
+    /// Read the password from a file
+    #[arg(long)]
+    password_file: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct UpdateArgs {
+    /// Port to connect to, defaults to the profile's port
+    #[arg(long, env = "IPASS_PORT")]
+    port: Option<u16>,
+    /// Website url of the account
+    #[arg()]
+    url: String,
+    /// User name of the account, defaults to the `usernames` setting of the domain
+    username: Option<String>,
+    /// Change the password, prompted for unless read with `--password-stdin` or `--password-file`
+    #[arg(long)]
+    new_password: bool,
+    #[command(flatten)]
+    input: PasswordInput,
+    /// Change the user name
+    #[arg(long)]
+    new_username: Option<String>,
+    /// Change the website url
+    #[arg(long)]
+    new_url: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct GenerateArgs {
+    /// Port to connect to when saving, defaults to the profile's port
+    #[arg(long, env = "IPASS_PORT")]
+    port: Option<u16>,
+    /// Kind of password to generate
+    #[arg(long, value_enum, default_value_t = pw::Style::Apple)]
+    style: pw::Style,
+    /// Number of characters of `characters` passwords
+    #[arg(long, default_value_t = 20)]
+    length: usize,
+    /// Character classes of `characters` passwords, each used at least once, not with
+    /// password rules [default: lower,upper,digits,symbols]
+    #[arg(long, value_enum, value_delimiter = ',')]
+    classes: Option<Vec<generate::CharClass>>,
+    /// Number of words of `diceware` passphrases
+    #[arg(long, default_value_t = 7)]
+    words: usize,
+    /// Separator between the words of `diceware` passphrases
+    #[arg(long, default_value = "-")]
+    separator: String,
+    /// Password rules the password must satisfy, in the syntax of the `passwordrules`
+    /// HTML attribute, e.g. "minlength: 8; required: lower, upper; required: digit;"
+    #[arg(long)]
+    rules: Option<String>,
+    /// Website url to look up bundled password rules for, defaults to the url given to `--save`
+    #[arg(long)]
+    url: Option<String>,
+    /// Save the generated password for this website url and user name
+    #[arg(long, num_args = 2, value_names = ["URL", "USERNAME"])]
+    save: Option<Vec<String>>,
+    /// Fail instead of replacing the password of an existing account when saving
+    #[arg(long, requires = "save")]
+    no_overwrite: bool,
+    /// Replace the password of an existing account without asking when saving
+    #[arg(long, short, requires = "save", conflicts_with = "no_overwrite")]
+    yes: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ProfileAddArgs {
+    /// Name of the profile
+    #[arg(value_parser = profile::parse_name)]
+    name: String,
+    /// Host the daemon runs on [default: 127.0.0.1]
+    #[arg(long)]
+    host: Option<String>,
+    /// Port the daemon listens on [default: 27389]
+    #[arg(long)]
+    port: Option<u16>,
+    /// Unix socket the local daemon listens on, instead of the host and port
+    #[arg(long, conflicts_with_all = ["host", "port"])]
+    socket: Option<PathBuf>,
+    /// Host browser to present to the password manager helper [default: arc]
+    #[arg(long, value_enum)]
+    browser: Option<types::Browser>,
+    /// Seconds to wait for the daemon to reply, unlike the global `--timeout` kept in the profile [default: 5]
+    #[arg(long)]
+    reply_timeout: Option<u64>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ProfileRemoveArgs {
+    /// Name of the profile
+    name: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ConfigKeyArgs {
+    /// Name of the setting, `usernames.<domain>` for the default user name of a domain
+    key: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ConfigSetArgs {
+    /// Name of the setting, `usernames.<domain>` for the default user name of a domain
+    key: String,
+    /// Value of the setting
+    value: String,
 }
 
 #[derive(Subcommand, Debug, Clone)]
@@ -100,12 +316,45 @@ enum Commands {
     Start(StartArgs),
     /// Authenticate CLI with daemon
     Auth(AuthArgs),
+    /// Show what the password manager helper supports
+    Capabilities(CapabilitiesArgs),
     /// Interact with website passwords
     #[command(subcommand)]
     Pw(PasswordCommands),
     /// Interact with one time passwords (OTP)
     #[command(subcommand)]
     Otp(OtpCommands),
+    /// Manage profiles for multiple daemons and sessions
+    #[command(subcommand)]
+    Profile(ProfileCommands),
+    /// Manage default settings of all commands
+    #[command(subcommand)]
+    Config(ConfigCommands),
+    /// Clear a value copied with `--clip` from the clipboard, reading it from stdin
+    #[command(hide = true)]
+    ClipClear(ClipClearArgs),
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum ConfigCommands {
+    /// List settings, including built-in defaults
+    List,
+    /// Print a setting
+    Get(ConfigKeyArgs),
+    /// Change a setting
+    Set(ConfigSetArgs),
+    /// Reset a setting to its built-in default
+    Unset(ConfigKeyArgs),
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum ProfileCommands {
+    /// List profiles
+    List,
+    /// Add a profile, or update an existing one
+    Add(ProfileAddArgs),
+    /// Remove a profile and its session
+    Remove(ProfileRemoveArgs),
 }
 
 #[derive(Subcommand, Debug, Clone)]
@@ -122,10 +371,14 @@ enum PasswordCommands {
     Get(GetArgs),
     /// Save password by domain and username
     Save(SaveArgs),
+    /// Change the password, user name or url of an account
+    Update(UpdateArgs),
+    /// Generate a password, and optionally save it
+    Generate(GenerateArgs),
 }
 
 #[tokio::main]
-async fn main() -> io::Result<()> {
+async fn main() -> ExitCode {
     let args = PassArgs::parse();
 
     env_logger::builder()
@@ -134,16 +387,147 @@ async fn main() -> io::Result<()> {
         .format_timestamp(None)
         .init();
 
+    let profile = args.profile.clone();
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            match hint(&err, &profile) {
+                Some(hint) => eprintln!("Error: {}, {}", err, hint),
+                None => eprintln!("Error: {}", err),
+            }
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
+
+/// The command ipass was run as, for messages telling what to run next.
+pub fn my_cli() -> String {
+    env::args()
+        .next()
+        .as_ref()
+        .map(Path::new)
+        .and_then(Path::to_str)
+        .map(String::from)
+        .unwrap_or("ipass".to_owned())
+}
+
+/// The command to authenticate `profile`.
+fn auth_command(profile: &str) -> String {
+    match profile {
+        DEFAULT_PROFILE => format!("{} auth", my_cli()),
+        name => format!("{} --profile {} auth", my_cli(), name),
+    }
+}
+
+/// What to run to fix `err`, appended to its message.
+fn hint(err: &IpassError, profile: &str) -> Option<String> {
+    match err {
+        IpassError::DaemonUnreachable(addr) => Some(format!(
+            "please run `{} start {}` on that host to start it",
+            my_cli(),
+            addr.start_args()
+        )),
+        IpassError::NotAuthenticated => Some(format!(
+            "please run `{}` to authenticate",
+            auth_command(profile)
+        )),
+        IpassError::SessionRejected => Some(format!(
+            "please run `{}` to authenticate again",
+            auth_command(profile)
+        )),
+        IpassError::WrongPin => Some(format!(
+            "please run `{}` again and enter the PIN shown by the password manager",
+            auth_command(profile)
+        )),
+        IpassError::Ambiguous(_) => {
+            Some("use `--output table` to see them and narrow down the query".to_owned())
+        }
+        _ => None,
+    }
+}
+
+async fn run(args: PassArgs) -> Result<()> {
+    if args.mlock {
+        secret::enable_mlock();
+    }
+
+    if let Some(path) = args.config {
+        paths::set_config_file(path);
+    }
+    let profile = || -> Result<Profile> {
+        let mut profile = ConfigFile::load()?
+            .profile(&args.profile)
+            .map_err(|err| match err {
+                IpassError::NotFound(message) => IpassError::NotFound(format!(
+                    "{}, please run `{} profile add {}` to create it",
+                    message,
+                    my_cli(),
+                    args.profile
+                )),
+                err => err,
+            })?;
+        profile.settings.timeout = args.timeout.or(profile.settings.timeout);
+        profile.settings.output = args.output.or(profile.settings.output);
+        Ok(profile)
+    };
+
     match args.cmd {
-        Commands::Start(args) => daemon::start(args).await,
-        Commands::Auth(args) => auth::auth(args).await,
-        Commands::Pw(commands) => match commands {
-            PasswordCommands::List(args) => pw::list(args).await,
-            PasswordCommands::Get(args) => pw::get(args).await,
-            PasswordCommands::Save(args) => pw::save(args).await,
+        Commands::Start(args) => daemon::start(args, &profile()?).await,
+        Commands::Auth(args) => auth::auth(args, &profile()?).await,
+        Commands::Capabilities(args) => capabilities::capabilities(args, &profile()?).await,
+        Commands::Pw(commands) => {
+            let profile = profile()?;
+            match commands {
+                PasswordCommands::List(args) => {
+                    auth::retry_after_auth(&profile, args.port, || pw::list(args.clone(), &profile))
+                        .await
+                }
+                PasswordCommands::Get(args) => {
+                    auth::retry_after_auth(&profile, args.port, || pw::get(args.clone(), &profile))
+                        .await
+                }
+                PasswordCommands::Save(args) => {
+                    // Read once, a retry after authenticating can't prompt or read stdin again.
+                    let password = if args.dry_run {
+                        SecretString::default()
+                    } else {
+                        pw::read_password(args.password.as_deref(), &args.input)?
+                    };
+                    auth::retry_after_auth(&profile, args.port, || {
+                        pw::save(args.clone(), password.clone(), &profile)
+                    })
+                    .await
+                }
+                PasswordCommands::Update(args) => {
+                    let update = pw::read_update(&args)?;
+                    auth::retry_after_auth(&profile, args.port, || {
+                        pw::update(args.clone(), update.clone(), &profile)
+                    })
+                    .await
+                }
+                PasswordCommands::Generate(args) => pw::generate(args, &profile).await,
+            }
+        }
+        Commands::Otp(commands) => {
+            let profile = profile()?;
+            match commands {
+                OtpCommands::Get(args) => {
+                    auth::retry_after_auth(&profile, args.port, || otp::get(args.clone(), &profile))
+                        .await
+                }
+            }
+        }
+        Commands::Profile(commands) => match commands {
+            ProfileCommands::List => profile::list(&args.profile),
+            ProfileCommands::Add(add_args) => profile::add(add_args),
+            ProfileCommands::Remove(args) => profile::remove(args),
         },
-        Commands::Otp(commands) => match commands {
-            OtpCommands::Get(args) => otp::get(args).await,
+        Commands::Config(commands) => match commands {
+            ConfigCommands::List => settings::list(),
+            ConfigCommands::Get(args) => settings::get(args),
+            ConfigCommands::Set(args) => settings::set(args),
+            ConfigCommands::Unset(args) => settings::unset(args),
         },
+        Commands::ClipClear(args) => clip::clear(args).await,
     }
 }
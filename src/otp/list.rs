@@ -1,60 +1,121 @@
-use log::{debug, log_enabled};
-use std::net::UdpSocket;
+use ipass::client::Client;
+use ipass::config::Profile;
+use ipass::error::{IpassError, Result};
+use ipass::types::OTPEntry;
+use ipass::{output, util};
 
-use serde_json::json;
-use tokio::io;
+use crate::pw::username_or_setting;
+use crate::{clip, OtpArgs};
 
-use crate::{config::PassConfig, types::*, OtpArgs};
-
-pub async fn get(args: OtpArgs) -> io::Result<()> {
-    let config = match PassConfig::load() {
-        Ok(config) => config,
-        Err(err) => return Err(err),
-    };
+pub async fn get(args: OtpArgs, profile: &Profile) -> Result<()> {
+    let username = username_or_setting(args.username, &args.url, profile);
+    let client = Client::connect(profile, args.port).with_session(profile.load_session()?);
+    let entries = filter(
+        client.get_otp(&args.url).await?,
+        username.as_deref(),
+        args.source.as_deref(),
+        args.latest,
+    );
+    if entries.is_empty() {
+        let mut message = format!("no one time code found for {}", args.url);
+        if let Some(username) = &username {
+            message.push_str(&format!(" and user {}", username));
+        }
+        if let Some(source) = &args.source {
+            message.push_str(&format!(" from source {}", source));
+        }
+        return Err(IpassError::NoMatch(message));
+    }
 
-    let urls = if args.url.starts_with("http://") || args.url.starts_with("https://") {
-        vec![args.url]
-    } else {
-        vec![format!("http://{}", args.url)]
-    };
-    let req = json!(GetOTPReq {
-        cmd: Cmd::DidFillOneTimeCode,
-        tab_id: 0,
-        frame_id: 0,
-        payload: GetOTPForURLPayload {
-            qid: "CmdDidFillOneTimeCode".to_owned(),
-            smsg: SMSGReq {
-                tid: config.username,
-                sdata: ActFrameURLsType {
-                    act: Action::Search,
-                    urls,
-                    typ: "oneTimeCodes".to_owned(),
-                },
-            },
+    if args.code_only || args.raw || args.clip {
+        let entry = util::single(entries, "one time code")?;
+        if args.clip {
+            clip::copy(entry.code.as_str(), &profile.settings)?;
+        } else {
+            output::print_value(entry.code.as_str(), args.raw)?;
         }
+    } else {
+        print!("{}", profile.settings.output().entries(&entries)?.as_str());
+    }
+
+    Ok(())
+}
+
+/// The codes of `entries` for `username` and from `source`, and only the first
+/// of them with `latest`. Codes not tied to a user, e.g. received by SMS, are
+/// kept for any user. Codes carry no time, so the first is taken as the most
+/// recent, assuming the helper lists them newest first.
+pub fn filter(
+    mut entries: Vec<OTPEntry>,
+    username: Option<&str>,
+    source: Option<&str>,
+    latest: bool,
+) -> Vec<OTPEntry> {
+    entries.retain(|entry| {
+        username.is_none_or(|username| entry.username.is_empty() || entry.username == username)
+            && source.is_none_or(|source| entry.source.eq_ignore_ascii_case(source))
     });
+    if latest {
+        entries.truncate(1);
+    }
+    entries
+}
 
-    let socket = UdpSocket::bind("127.0.0.1:0").expect("bind to address");
-    socket
-        .send_to(
-            req.to_string().as_bytes(),
-            format!("127.0.0.1:{}", args.port),
-        )
-        .expect("send request to socket");
-
-    let mut buf = [0; 65536];
-    let (len, _) = socket.recv_from(&mut buf).expect("receive data");
-
-    if log_enabled!(log::Level::Debug) {
-        debug!(
-            "OTP response: {}",
-            std::str::from_utf8(&buf[..len]).unwrap()
-        );
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(username: &str, source: &str, code: &str) -> OTPEntry {
+        OTPEntry {
+            username: username.to_owned(),
+            source: source.to_owned(),
+            domain: "github.com".to_owned(),
+            code: code.to_owned().into(),
+        }
     }
 
-    let res = serde_json::from_slice::<GetOTPForURLRes>(&buf[..len])?;
+    fn entries() -> Vec<OTPEntry> {
+        vec![
+            entry("octocat", "totp", "111111"),
+            entry("", "sms", "222222"),
+            entry("monalisa", "totp", "333333"),
+            entry("octocat", "mail", "444444"),
+        ]
+    }
 
-    println!("{}", json!(res.payload.smsg.sdata));
+    fn codes(entries: Vec<OTPEntry>) -> Vec<String> {
+        entries.iter().map(|entry| entry.code.to_string()).collect()
+    }
 
-    Ok(())
+    #[test]
+    fn test_filter_by_username_keeps_codes_of_no_user() {
+        assert_eq!(
+            codes(filter(entries(), Some("octocat"), None, false)),
+            ["111111", "222222", "444444"]
+        );
+        assert_eq!(codes(filter(entries(), None, None, false)).len(), 4);
+    }
+
+    #[test]
+    fn test_filter_by_source_ignores_case() {
+        assert_eq!(
+            codes(filter(entries(), None, Some("TOTP"), false)),
+            ["111111", "333333"]
+        );
+        assert_eq!(
+            codes(filter(entries(), Some("monalisa"), Some("sms"), false)),
+            ["222222"]
+        );
+        assert!(filter(entries(), None, Some("push"), false).is_empty());
+    }
+
+    #[test]
+    fn test_latest_is_the_first_match() {
+        assert_eq!(codes(filter(entries(), None, None, true)), ["111111"]);
+        assert_eq!(
+            codes(filter(entries(), Some("monalisa"), Some("totp"), true)),
+            ["333333"]
+        );
+        assert!(filter(Vec::new(), None, None, true).is_empty());
+    }
 }
@@ -1,3 +1,2 @@
 mod list;
 pub use crate::otp::list::*;
-
@@ -0,0 +1,500 @@
+use std::fmt;
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::error::Result;
+use crate::types::{DeleteOutcome, LoginEntry, OTPEntry, SaveOutcome, SavePlan, UpdateOutcome};
+
+/// Format in which commands print their results.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// A single JSON document, `{"entries": [...]}` for lists
+    #[default]
+    Json,
+    /// One JSON object per line
+    JsonLines,
+    /// Aligned columns with a header, for humans
+    Table,
+    /// Tab separated columns with a header
+    Tsv,
+    /// Only the most useful column, e.g. the password, one per line
+    Plain,
+    /// The JSON document as YAML
+    Yaml,
+}
+
+/// A result that can be printed as a row in the text formats.
+pub trait Tabular: Serialize {
+    /// Names of the columns.
+    const COLUMNS: &'static [&'static str];
+    /// Index of the column printed by [`OutputFormat::Plain`].
+    const PLAIN: usize;
+
+    /// Cells of the row, one per column.
+    fn row(&self) -> Zeroizing<Vec<String>>;
+}
+
+impl OutputFormat {
+    /// Renders a list of results, including a trailing newline unless empty.
+    pub fn entries<T: Tabular>(self, entries: &[T]) -> Result<Zeroizing<String>> {
+        #[derive(Serialize)]
+        struct Entries<'a, T> {
+            entries: &'a [T],
+        }
+
+        self.render(entries, &Entries { entries })
+    }
+
+    /// Renders a single result, including a trailing newline.
+    pub fn record<T: Tabular>(self, record: &T) -> Result<Zeroizing<String>> {
+        self.render(std::slice::from_ref(record), record)
+    }
+
+    /// Writes straight into a buffer that is wiped, so no copy of a secret
+    /// is left behind in intermediate strings.
+    fn render<T: Tabular>(
+        self,
+        rows: &[T],
+        document: &impl Serialize,
+    ) -> Result<Zeroizing<String>> {
+        let mut out = SecretBuffer::default();
+        match self {
+            Self::Json => {
+                serde_json::to_writer(&mut out, document)?;
+                out.write_all(b"\n")?;
+            }
+            Self::JsonLines => {
+                for row in rows {
+                    serde_json::to_writer(&mut out, row)?;
+                    out.write_all(b"\n")?;
+                }
+            }
+            Self::Yaml => {
+                let mut document = serde_json::to_value(document)?;
+                let written = write_yaml(&mut out, &document, 0, false);
+                wipe(&mut document);
+                written?;
+            }
+            Self::Plain => {
+                for row in rows {
+                    out.write_all(row.row()[T::PLAIN].as_bytes())?;
+                    out.write_all(b"\n")?;
+                }
+            }
+            Self::Tsv => {
+                let rows = rows.iter().map(Tabular::row).collect::<Vec<_>>();
+                for cells in std::iter::once(&header::<T>()).chain(&rows) {
+                    for (i, cell) in cells.iter().enumerate() {
+                        if i > 0 {
+                            out.write_all(b"\t")?;
+                        }
+                        write_tsv(&mut out, cell)?;
+                    }
+                    out.write_all(b"\n")?;
+                }
+            }
+            Self::Table => {
+                let rows = rows.iter().map(Tabular::row).collect::<Vec<_>>();
+                let header = header::<T>();
+                let widths = (0..T::COLUMNS.len())
+                    .map(|i| {
+                        std::iter::once(&header)
+                            .chain(&rows)
+                            .map(|cells| cells[i].chars().count())
+                            .max()
+                            .unwrap_or(0)
+                    })
+                    .collect::<Vec<_>>();
+                for cells in std::iter::once(&header).chain(&rows) {
+                    // Padding is only written before the next cell, so lines don't end in spaces.
+                    let mut padding = 0;
+                    for (i, (cell, width)) in cells.iter().zip(&widths).enumerate() {
+                        if i > 0 {
+                            padding += 2;
+                        }
+                        if !cell.is_empty() {
+                            write!(out, "{:1$}", "", padding)?;
+                            out.write_all(cell.as_bytes())?;
+                            padding = 0;
+                        }
+                        padding += width - cell.chars().count();
+                    }
+                    out.write_all(b"\n")?;
+                }
+            }
+        }
+        Ok(out.into_string())
+    }
+}
+
+/// Output buffer that wipes its old allocation whenever it grows.
+#[derive(Default)]
+struct SecretBuffer(Zeroizing<Vec<u8>>);
+
+impl SecretBuffer {
+    fn into_string(mut self) -> Zeroizing<String> {
+        let bytes = std::mem::take(&mut *self.0);
+        Zeroizing::new(String::from_utf8(bytes).expect("only strings are written"))
+    }
+}
+
+impl Write for SecretBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.0.len() + buf.len();
+        if len > self.0.capacity() {
+            let mut grown = Zeroizing::new(Vec::with_capacity(len.max(2 * self.0.capacity())));
+            grown.extend_from_slice(&self.0);
+            self.0 = grown;
+        }
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes `value` as block style YAML, all strings double quoted. Written by
+/// hand as serde_yaml is no longer maintained, and to only write to `out`.
+/// `inline` means the first line continues one started by the caller.
+fn write_yaml(
+    out: &mut SecretBuffer,
+    value: &serde_json::Value,
+    indent: usize,
+    inline: bool,
+) -> io::Result<()> {
+    use serde_json::Value;
+
+    let is_block = |value: &Value| match value {
+        Value::Object(map) => !map.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        _ => false,
+    };
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (i, (key, value)) in map.iter().enumerate() {
+                if i > 0 || !inline {
+                    write!(out, "{:1$}", "", indent)?;
+                }
+                if key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                    out.write_all(key.as_bytes())?;
+                } else {
+                    write_yaml_str(out, key)?;
+                }
+                out.write_all(b":")?;
+                if is_block(value) {
+                    out.write_all(b"\n")?;
+                    write_yaml(out, value, indent + 2, false)?;
+                } else {
+                    out.write_all(b" ")?;
+                    write_yaml(out, value, indent + 2, true)?;
+                }
+            }
+            Ok(())
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 || !inline {
+                    write!(out, "{:1$}", "", indent)?;
+                }
+                out.write_all(b"- ")?;
+                write_yaml(out, item, indent + 2, true)?;
+            }
+            Ok(())
+        }
+        Value::Object(_) => out.write_all(b"{}\n"),
+        Value::Array(_) => out.write_all(b"[]\n"),
+        Value::String(string) => {
+            write_yaml_str(out, string)?;
+            out.write_all(b"\n")
+        }
+        scalar => writeln!(out, "{}", scalar),
+    }
+}
+
+/// Writes a double quoted YAML string, escaping what YAML doesn't allow as is.
+fn write_yaml_str(out: &mut SecretBuffer, string: &str) -> io::Result<()> {
+    out.write_all(b"\"")?;
+    let mut start = 0;
+    for (i, c) in string.char_indices() {
+        let escaped = match c {
+            '"' => "\\\"",
+            '\\' => "\\\\",
+            '\n' => "\\n",
+            '\t' => "\\t",
+            '\r' => "\\r",
+            c if c.is_control() || c == '\u{feff}' => "",
+            _ => continue,
+        };
+        out.write_all(&string.as_bytes()[start..i])?;
+        if escaped.is_empty() {
+            write!(out, "\\u{:04X}", c as u32)?;
+        } else {
+            out.write_all(escaped.as_bytes())?;
+        }
+        start = i + c.len_utf8();
+    }
+    out.write_all(&string.as_bytes()[start..])?;
+    out.write_all(b"\"")
+}
+
+/// Wipes the strings of a document serialized to render it.
+fn wipe(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::String(string) => string.zeroize(),
+        serde_json::Value::Array(items) => items.iter_mut().for_each(wipe),
+        serde_json::Value::Object(map) => map.values_mut().for_each(wipe),
+        _ => {}
+    }
+}
+
+/// Field of an account printed by itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Field {
+    Password,
+    User,
+    /// The sites of the account, separated by commas
+    Sites,
+}
+
+impl LoginEntry {
+    pub fn field(&self, field: Field) -> Zeroizing<String> {
+        Zeroizing::new(match field {
+            Field::Password => self.password.as_str().to_owned(),
+            Field::User => self.user.clone(),
+            Field::Sites => self.sites.join(","),
+        })
+    }
+}
+
+/// Prints a single value, without a trailing newline if `raw`.
+pub fn print_value(value: &str, raw: bool) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    if raw {
+        write!(stdout, "{}", value)?;
+    } else {
+        writeln!(stdout, "{}", value)?;
+    }
+    Ok(stdout.flush()?)
+}
+
+fn header<T: Tabular>() -> Zeroizing<Vec<String>> {
+    Zeroizing::new(T::COLUMNS.iter().map(|name| name.to_uppercase()).collect())
+}
+
+/// Writes a cell keeping its row on one line, tabs and newlines are written as `\t` and `\n`.
+fn write_tsv(out: &mut SecretBuffer, cell: &str) -> io::Result<()> {
+    let mut start = 0;
+    for (i, c) in cell.char_indices() {
+        let escaped = match c {
+            '\\' => "\\\\",
+            '\t' => "\\t",
+            '\n' => "\\n",
+            '\r' => "\\r",
+            _ => continue,
+        };
+        out.write_all(&cell.as_bytes()[start..i])?;
+        out.write_all(escaped.as_bytes())?;
+        start = i + 1;
+    }
+    out.write_all(&cell.as_bytes()[start..])
+}
+
+impl Tabular for LoginEntry {
+    const COLUMNS: &'static [&'static str] = &["user", "sites", "password"];
+    const PLAIN: usize = 2;
+
+    fn row(&self) -> Zeroizing<Vec<String>> {
+        Zeroizing::new(vec![
+            self.user.clone(),
+            self.sites.join(","),
+            self.password.as_str().to_owned(),
+        ])
+    }
+}
+
+impl Tabular for OTPEntry {
+    const COLUMNS: &'static [&'static str] = &["username", "domain", "source", "code"];
+    const PLAIN: usize = 3;
+
+    fn row(&self) -> Zeroizing<Vec<String>> {
+        Zeroizing::new(vec![
+            self.username.clone(),
+            self.domain.clone(),
+            self.source.clone(),
+            self.code.as_str().to_owned(),
+        ])
+    }
+}
+
+/// The `outcome` tag of a serialized outcome and its message.
+fn outcome_row<T: Serialize + fmt::Display>(outcome: &T) -> Zeroizing<Vec<String>> {
+    tagged_row(outcome, "outcome")
+}
+
+fn tagged_row<T: Serialize + fmt::Display>(value: &T, tag: &str) -> Zeroizing<Vec<String>> {
+    let name = serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value[tag].as_str().map(str::to_owned))
+        .unwrap_or_default();
+    Zeroizing::new(vec![name, value.to_string()])
+}
+
+impl Tabular for SavePlan {
+    const COLUMNS: &'static [&'static str] = &["plan", "message"];
+    const PLAIN: usize = 0;
+
+    fn row(&self) -> Zeroizing<Vec<String>> {
+        tagged_row(self, "plan")
+    }
+}
+
+impl Tabular for SaveOutcome {
+    const COLUMNS: &'static [&'static str] = &["outcome", "message"];
+    const PLAIN: usize = 0;
+
+    fn row(&self) -> Zeroizing<Vec<String>> {
+        outcome_row(self)
+    }
+}
+
+impl Tabular for DeleteOutcome {
+    const COLUMNS: &'static [&'static str] = &["outcome", "message"];
+    const PLAIN: usize = 0;
+
+    fn row(&self) -> Zeroizing<Vec<String>> {
+        outcome_row(self)
+    }
+}
+
+impl Tabular for UpdateOutcome {
+    const COLUMNS: &'static [&'static str] = &["outcome", "message"];
+    const PLAIN: usize = 0;
+
+    fn row(&self) -> Zeroizing<Vec<String>> {
+        outcome_row(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<LoginEntry> {
+        vec![
+            LoginEntry {
+                user: "octocat".to_owned(),
+                sites: vec!["github.com".to_owned(), "gist.github.com".to_owned()],
+                password: "hunter2".to_owned().into(),
+            },
+            LoginEntry {
+                user: "a".to_owned(),
+                sites: vec!["github.com".to_owned()],
+                password: "tab\there".to_owned().into(),
+            },
+        ]
+    }
+
+    fn render(format: OutputFormat) -> String {
+        format.entries(&entries()).unwrap().as_str().to_owned()
+    }
+
+    #[test]
+    fn test_json_wraps_entries() {
+        let json: serde_json::Value = serde_json::from_str(&render(OutputFormat::Json)).unwrap();
+        assert_eq!(json["entries"][0]["user"], "octocat");
+        assert_eq!(json["entries"][1]["password"], "tab\there");
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let out = render(OutputFormat::JsonLines);
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            r#"{"user":"octocat","sites":["github.com","gist.github.com"],"password":"hunter2"}"#
+        );
+    }
+
+    #[test]
+    fn test_table_aligns_columns() {
+        assert_eq!(
+            render(OutputFormat::Table),
+            "USER     SITES                       PASSWORD\n\
+             octocat  github.com,gist.github.com  hunter2\n\
+             a        github.com                  tab\there\n"
+        );
+    }
+
+    #[test]
+    fn test_tsv_escapes_tabs() {
+        assert_eq!(
+            render(OutputFormat::Tsv),
+            "USER\tSITES\tPASSWORD\n\
+             octocat\tgithub.com,gist.github.com\thunter2\n\
+             a\tgithub.com\ttab\\there\n"
+        );
+    }
+
+    #[test]
+    fn test_plain_prints_passwords() {
+        assert_eq!(render(OutputFormat::Plain), "hunter2\ntab\there\n");
+    }
+
+    #[test]
+    fn test_yaml() {
+        assert_eq!(
+            render(OutputFormat::Yaml),
+            "entries:\n\
+             \x20 - password: \"hunter2\"\n\
+             \x20   sites:\n\
+             \x20     - \"github.com\"\n\
+             \x20     - \"gist.github.com\"\n\
+             \x20   user: \"octocat\"\n\
+             \x20 - password: \"tab\\there\"\n\
+             \x20   sites:\n\
+             \x20     - \"github.com\"\n\
+             \x20   user: \"a\"\n"
+        );
+        assert_eq!(
+            OutputFormat::Yaml
+                .entries::<LoginEntry>(&[])
+                .unwrap()
+                .as_str(),
+            "entries: []\n"
+        );
+    }
+
+    #[test]
+    fn test_yaml_escapes_strings() {
+        let mut out = SecretBuffer::default();
+        write_yaml_str(&mut out, "a\"b\\c\u{7}\u{85}\u{feff}é").unwrap();
+        assert_eq!(
+            out.into_string().as_str(),
+            r#""a\"b\\c\u0007\u0085\uFEFFé""#
+        );
+    }
+
+    #[test]
+    fn test_buffer_grows() {
+        let mut out = SecretBuffer::default();
+        for _ in 0..100 {
+            out.write_all(b"0123456789").unwrap();
+        }
+        assert_eq!(out.into_string().as_str(), "0123456789".repeat(100));
+    }
+
+    #[test]
+    fn test_record_is_not_wrapped() {
+        let out = OutputFormat::Json.record(&SaveOutcome::Created).unwrap();
+        assert_eq!(out.as_str(), "{\"outcome\":\"created\"}\n");
+        let out = OutputFormat::Plain.record(&SaveOutcome::Created).unwrap();
+        assert_eq!(out.as_str(), "created\n");
+    }
+}
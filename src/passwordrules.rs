@@ -0,0 +1,336 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Deserialize;
+
+use crate::error::{IpassError, Result};
+use crate::util;
+
+/// Rules of some well known sites, an MIT licensed excerpt of Apple's
+/// password-manager-resources, see `password-rules.LICENSE` for its provenance.
+const DATABASE: &str = include_str!("password-rules.json");
+
+const SPECIAL: &str = "-~!@#$%^&*_+=`|(){}[:;\"'<>,.?] ";
+
+#[derive(Deserialize)]
+struct Quirks {
+    #[serde(rename = "password-rules")]
+    password_rules: String,
+}
+
+/// Constraints a site puts on passwords, in the syntax of the `passwordrules`
+/// attribute, e.g. `minlength: 8; required: lower, upper; required: digit;`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PasswordRules {
+    /// Sets of which the password must contain at least one character each.
+    pub required: Vec<BTreeSet<char>>,
+    /// Characters the password may contain besides the required ones.
+    pub allowed: BTreeSet<char>,
+    /// Whether any character is allowed.
+    pub unicode: bool,
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    /// How often the same character may follow itself.
+    pub max_consecutive: Option<usize>,
+}
+
+impl PasswordRules {
+    pub fn parse(input: &str) -> Result<Self> {
+        Parser { input, pos: 0 }.rules()
+    }
+
+    /// The rules of the bundled database for the domain of `url`, also matching
+    /// its parent domains.
+    pub fn for_url(url: &str) -> Result<Option<Self>> {
+        let database: BTreeMap<String, Quirks> = serde_json::from_str(DATABASE)?;
+        let mut domain = util::domain(url);
+        loop {
+            if let Some(quirks) = database.get(domain) {
+                return Self::parse(&quirks.password_rules).map(Some);
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) => domain = parent,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Characters a password may contain, all printable ASCII when nothing is specified.
+    pub fn allowed_chars(&self) -> BTreeSet<char> {
+        if self.required.is_empty() && self.allowed.is_empty() {
+            return (' '..='~').collect();
+        }
+        let mut allowed = self.allowed.clone();
+        allowed.extend(self.required.iter().flatten());
+        allowed
+    }
+
+    /// Whether `password` satisfies every rule.
+    pub fn check(&self, password: &str) -> bool {
+        let length = password.chars().count();
+        let allowed = self.allowed_chars();
+        length >= self.min_length.unwrap_or(0)
+            && self.max_length.is_none_or(|max| length <= max)
+            && (self.unicode || password.chars().all(|c| allowed.contains(&c)))
+            && self
+                .required
+                .iter()
+                .all(|set| password.chars().any(|c| set.contains(&c)))
+            && self
+                .max_consecutive
+                .is_none_or(|max| longest_run(password) <= max)
+    }
+}
+
+fn longest_run(password: &str) -> usize {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut last = None;
+    for c in password.chars() {
+        run = if last == Some(c) { run + 1 } else { 1 };
+        longest = longest.max(run);
+        last = Some(c);
+    }
+    longest
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn rules(&mut self) -> Result<PasswordRules> {
+        let mut rules = PasswordRules::default();
+        loop {
+            self.skip_whitespace();
+            if self.peek().is_none() {
+                return Ok(rules);
+            }
+            let name = self.identifier()?.to_ascii_lowercase();
+            self.skip_whitespace();
+            self.expect(':')?;
+            self.skip_whitespace();
+            match name.as_str() {
+                "required" => {
+                    let (set, unicode) = self.classes()?;
+                    rules.unicode |= unicode;
+                    if !set.is_empty() {
+                        rules.required.push(set);
+                    }
+                }
+                "allowed" => {
+                    let (set, unicode) = self.classes()?;
+                    rules.unicode |= unicode;
+                    rules.allowed.extend(set);
+                }
+                "minlength" => {
+                    let value = self.number()?;
+                    rules.min_length = Some(rules.min_length.map_or(value, |min| min.max(value)));
+                }
+                "maxlength" => {
+                    let value = self.number()?;
+                    rules.max_length = Some(rules.max_length.map_or(value, |max| max.min(value)));
+                }
+                "max-consecutive" => {
+                    let value = self.number()?;
+                    rules.max_consecutive =
+                        Some(rules.max_consecutive.map_or(value, |max| max.min(value)));
+                }
+                // Unknown properties are ignored, as browsers do.
+                _ => {
+                    while self.peek().is_some_and(|c| c != ';') {
+                        self.next();
+                    }
+                }
+            }
+            self.skip_whitespace();
+            match self.peek() {
+                Some(';') => self.pos += 1,
+                None => return Ok(rules),
+                Some(_) => return Err(self.error("expected `;`")),
+            }
+        }
+    }
+
+    /// A comma separated list of character classes, and whether it includes `unicode`.
+    fn classes(&mut self) -> Result<(BTreeSet<char>, bool)> {
+        let mut set = BTreeSet::new();
+        let mut unicode = false;
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some('[') {
+                set.extend(self.custom_class()?);
+            } else {
+                match self.identifier()?.to_ascii_lowercase().as_str() {
+                    "upper" => set.extend('A'..='Z'),
+                    "lower" => set.extend('a'..='z'),
+                    "digit" => set.extend('0'..='9'),
+                    "special" => set.extend(SPECIAL.chars()),
+                    "ascii-printable" => set.extend(' '..='~'),
+                    "unicode" => unicode = true,
+                    // Unknown classes are ignored, as browsers do.
+                    _ => {}
+                }
+            }
+            self.skip_whitespace();
+            if self.peek() != Some(',') {
+                return Ok((set, unicode));
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// Characters between brackets, where `]` right after the opening bracket is literal.
+    fn custom_class(&mut self) -> Result<BTreeSet<char>> {
+        self.expect('[')?;
+        let mut set = BTreeSet::new();
+        let mut first = true;
+        loop {
+            match self.next() {
+                Some(']') if !first => return Ok(set),
+                Some(c) if c.is_ascii_graphic() || c == ' ' => {
+                    set.insert(c);
+                }
+                Some(_) => {
+                    return Err(self.error("only printable ASCII is allowed between brackets"))
+                }
+                None => return Err(self.error("expected `]`")),
+            }
+            first = false;
+        }
+    }
+
+    fn identifier(&mut self) -> Result<&str> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '-')
+        {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(self.error("expected a name"));
+        }
+        Ok(&self.input[start..self.pos])
+    }
+
+    fn number(&mut self) -> Result<usize> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        self.input[start..self.pos]
+            .parse()
+            .map_err(|_| self.error("expected a number"))
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", expected)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.next();
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn error(&self, reason: &str) -> IpassError {
+        IpassError::InvalidInput(format!(
+            "invalid password rules at character {}: {}",
+            self.pos + 1,
+            reason
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let rules = PasswordRules::parse(
+            "minlength: 8; maxlength: 20; required: lower, upper; required: digit; \
+             allowed: [-().&@?'#,/\"+]; max-consecutive: 2;",
+        )
+        .unwrap();
+        assert_eq!(rules.min_length, Some(8));
+        assert_eq!(rules.max_length, Some(20));
+        assert_eq!(rules.max_consecutive, Some(2));
+        assert_eq!(rules.required.len(), 2);
+        assert_eq!(rules.required[0].len(), 52);
+        assert_eq!(rules.required[1], ('0'..='9').collect());
+        assert_eq!(rules.allowed, "-().&@?'#,/\"+".chars().collect());
+    }
+
+    #[test]
+    fn test_parse_is_lenient_like_browsers() {
+        let rules = PasswordRules::parse("MinLength:6;future: x y;allowed: upper, emoji").unwrap();
+        assert_eq!(rules.min_length, Some(6));
+        assert_eq!(rules.allowed, ('A'..='Z').collect());
+        let rules = PasswordRules::parse("required: []]; minlength: 4; minlength: 10").unwrap();
+        assert_eq!(rules.required, vec![BTreeSet::from([']'])]);
+        assert_eq!(rules.min_length, Some(10));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for input in [
+            "minlength 8",
+            "minlength: eight",
+            "required: [abc",
+            "required: lower upper",
+        ] {
+            assert!(
+                matches!(
+                    PasswordRules::parse(input),
+                    Err(IpassError::InvalidInput(_))
+                ),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let rules = PasswordRules::parse(
+            "minlength: 4; maxlength: 6; required: digit; allowed: lower; max-consecutive: 2",
+        )
+        .unwrap();
+        assert!(rules.check("ab12"));
+        assert!(!rules.check("abc"));
+        assert!(!rules.check("abcdefg1"));
+        assert!(!rules.check("abcd"));
+        assert!(!rules.check("aaa1"));
+        assert!(!rules.check("ab1é"));
+        assert!(PasswordRules::default().check("any thing~"));
+    }
+
+    #[test]
+    fn test_database() {
+        let database: BTreeMap<String, Quirks> = serde_json::from_str(DATABASE).unwrap();
+        for (domain, quirks) in &database {
+            PasswordRules::parse(&quirks.password_rules)
+                .unwrap_or_else(|err| panic!("{}: {}", domain, err));
+        }
+        let rules = PasswordRules::for_url("https://secure.chase.com/login").unwrap();
+        assert_eq!(rules.unwrap().max_consecutive, Some(2));
+        assert_eq!(PasswordRules::for_url("example.com").unwrap(), None);
+    }
+}
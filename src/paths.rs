@@ -0,0 +1,91 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::error::{IpassError, Result};
+
+/// Config file given on the command line or in `IPASS_CONFIG`.
+static CONFIG_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+pub fn set_config_file(path: PathBuf) {
+    CONFIG_FILE.set(path).expect("config file is only set once");
+}
+
+/// Whether the config file was chosen by the user, whose directory ipass doesn't own.
+pub fn is_config_file_overridden() -> bool {
+    CONFIG_FILE.get().is_some()
+}
+
+/// The config file holding profiles, `$XDG_CONFIG_HOME/ipass/config.json` by default.
+pub fn config_file() -> Result<PathBuf> {
+    match CONFIG_FILE.get() {
+        Some(path) => Ok(path.clone()),
+        None => Ok(xdg_dir("XDG_CONFIG_HOME", ".config")?.join("config.json")),
+    }
+}
+
+/// The file holding sessions. Sessions don't outlive the helper, so they are
+/// kept in `$XDG_RUNTIME_DIR` when available and in `$XDG_STATE_HOME` otherwise,
+/// or next to a config file given by the user so its profiles keep their own.
+pub fn sessions_file() -> Result<PathBuf> {
+    if let Some(path) = CONFIG_FILE.get() {
+        return Ok(sessions_file_next_to(path));
+    }
+    let dir = match absolute_var("XDG_RUNTIME_DIR") {
+        Some(dir) => dir.join("ipass"),
+        None => xdg_dir("XDG_STATE_HOME", ".local/state")?,
+    };
+    Ok(dir.join("sessions.json"))
+}
+
+/// `work.json` keeps its sessions in `work.sessions.json`.
+fn sessions_file_next_to(config_file: &Path) -> PathBuf {
+    config_file.with_extension("sessions.json")
+}
+
+/// Where the config file was kept before XDG base directories were supported.
+pub fn legacy_config_file() -> Result<PathBuf> {
+    Ok(home_dir()?.join(".ipass").join("config.json"))
+}
+
+fn xdg_dir(var: &str, fallback: &str) -> Result<PathBuf> {
+    match absolute_var(var) {
+        Some(dir) => Ok(dir.join("ipass")),
+        None => Ok(home_dir()?.join(fallback).join("ipass")),
+    }
+}
+
+/// Reads a directory from the environment, relative paths are invalid per the XDG spec.
+fn absolute_var(var: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
+
+fn home_dir() -> Result<PathBuf> {
+    absolute_var("HOME").ok_or_else(|| {
+        IpassError::NotFound(
+            "cannot find the home directory, please set HOME or the XDG base directory variables"
+                .to_owned(),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sessions_file_next_to_config_file() {
+        for (config, sessions) in [
+            ("work.json", "work.sessions.json"),
+            ("/etc/ipass/work", "/etc/ipass/work.sessions.json"),
+            ("dir.d/config.json", "dir.d/config.sessions.json"),
+        ] {
+            assert_eq!(
+                sessions_file_next_to(Path::new(config)),
+                Path::new(sessions)
+            );
+        }
+    }
+}
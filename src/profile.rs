@@ -0,0 +1,207 @@
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::json;
+
+use ipass::error::{IpassError, Result};
+
+use crate::{ProfileAddArgs, ProfileRemoveArgs};
+use ipass::config::{ConfigFile, Profile, DEFAULT_PROFILE};
+use ipass::types::Browser;
+
+#[derive(Serialize)]
+struct ProfileSummary<'a> {
+    name: &'a str,
+    host: &'a str,
+    port: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    socket: Option<&'a Path>,
+    browser: Browser,
+    timeout: u64,
+    authenticated: bool,
+    active: bool,
+}
+
+/// Profile names end up in keychain commands, so keep them to a safe alphabet.
+pub fn parse_name(name: &str) -> std::result::Result<String, String> {
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        Ok(name.to_owned())
+    } else {
+        Err("profile names may only contain letters, digits, `-`, `_` and `.`".to_owned())
+    }
+}
+
+/// Lists the profiles, `active` is the one selected on the command line.
+pub fn list(active: &str) -> Result<()> {
+    let mut config = ConfigFile::load()?;
+    config.profile_mut(DEFAULT_PROFILE);
+
+    let resolved = config
+        .profiles
+        .keys()
+        .map(|name| Ok((name, config.profile(name)?)))
+        .collect::<Result<Vec<_>>>()?;
+    let profiles = resolved
+        .iter()
+        .map(|(name, profile)| ProfileSummary {
+            name,
+            host: profile.settings.host(),
+            port: profile.settings.port(),
+            socket: profile.settings.socket.as_deref(),
+            browser: profile.browser,
+            timeout: profile.settings.timeout().as_secs(),
+            authenticated: profile.session.is_some(),
+            active: *name == active,
+        })
+        .collect::<Vec<_>>();
+    println!("{}", json!(profiles));
+
+    Ok(())
+}
+
+pub fn add(args: ProfileAddArgs) -> Result<()> {
+    let mut config = ConfigFile::load()?;
+    update(config.profile_mut(&args.name), args);
+    config.save()
+}
+
+pub fn remove(args: ProfileRemoveArgs) -> Result<()> {
+    let mut config = ConfigFile::load()?;
+    remove_from(&mut config, &args.name)?;
+    config.save()
+}
+
+/// Applies the options given to `profile add`, keeping the others.
+fn update(profile: &mut Profile, args: ProfileAddArgs) {
+    if args.host.is_some() || args.port.is_some() || args.socket.is_some() {
+        // The session belongs to the daemon the profile pointed to before.
+        profile.session = None;
+    }
+    if args.host.is_some() || args.port.is_some() {
+        profile.settings.socket = None;
+    }
+    if let Some(host) = args.host {
+        profile.settings.host = Some(host);
+    }
+    if let Some(port) = args.port {
+        profile.settings.port = Some(port);
+    }
+    if let Some(socket) = args.socket {
+        profile.settings.socket = Some(socket);
+    }
+    if let Some(browser) = args.browser {
+        profile.browser = browser;
+    }
+    if let Some(timeout) = args.reply_timeout {
+        profile.settings.timeout = Some(timeout);
+    }
+}
+
+fn remove_from(config: &mut ConfigFile, name: &str) -> Result<()> {
+    match config.profiles.remove(name) {
+        Some(_) => Ok(()),
+        None => Err(IpassError::NotFound(format!(
+            "profile `{}` does not exist",
+            name
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use ipass::config::PassConfig;
+
+    use super::*;
+
+    fn add_args(name: &str) -> ProfileAddArgs {
+        ProfileAddArgs {
+            name: name.to_owned(),
+            host: None,
+            port: None,
+            socket: None,
+            browser: None,
+            reply_timeout: None,
+        }
+    }
+
+    fn authenticated() -> Profile {
+        Profile {
+            session: Some(PassConfig::new("u".to_owned(), "a2V5".to_owned().into())),
+            ..Profile::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_name() {
+        assert!(parse_name("work-2.mac_mini").is_ok());
+        for name in ["", "a b", "a;b", "../a", "$(id)"] {
+            assert!(parse_name(name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_add_sets_only_given_options() {
+        let mut profile = authenticated();
+        profile.settings.port = Some(27390);
+        update(
+            &mut profile,
+            ProfileAddArgs {
+                browser: Some(Browser::Chrome),
+                reply_timeout: Some(10),
+                ..add_args("work")
+            },
+        );
+        assert_eq!(profile.settings.port, Some(27390));
+        assert_eq!(profile.settings.host, None);
+        assert_eq!(profile.browser, Browser::Chrome);
+        assert_eq!(profile.settings.timeout, Some(10));
+        assert!(profile.session.is_some());
+    }
+
+    #[test]
+    fn test_changing_the_daemon_forgets_the_session() {
+        let mut profile = authenticated();
+        update(
+            &mut profile,
+            ProfileAddArgs {
+                socket: Some(PathBuf::from("/run/ipass.sock")),
+                ..add_args("work")
+            },
+        );
+        assert!(profile.session.is_none());
+        assert_eq!(
+            profile.settings.socket.as_deref(),
+            Some(Path::new("/run/ipass.sock"))
+        );
+
+        profile.session = authenticated().session;
+        update(
+            &mut profile,
+            ProfileAddArgs {
+                port: Some(27391),
+                ..add_args("work")
+            },
+        );
+        assert!(profile.session.is_none());
+        assert_eq!(profile.settings.port, Some(27391));
+        assert_eq!(profile.settings.socket, None);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut config = ConfigFile::default();
+        config.profile_mut("work");
+        remove_from(&mut config, "work").unwrap();
+        assert!(config.profiles.is_empty());
+        assert!(matches!(
+            remove_from(&mut config, "work"),
+            Err(IpassError::NotFound(_))
+        ));
+    }
+}
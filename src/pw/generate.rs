@@ -0,0 +1,119 @@
+use clap::ValueEnum;
+use ipass::client::Client;
+use ipass::config::Profile;
+use ipass::error::{IpassError, Result};
+use ipass::generate::{self, CharClass};
+use ipass::output;
+use ipass::passwordrules::PasswordRules;
+use log::debug;
+
+use crate::auth;
+use crate::pw::save;
+use crate::GenerateArgs;
+
+/// Kinds of generated passwords.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Style {
+    /// `xxxxxx-xxxxxx-xxxxxx` like the strong passwords Safari suggests
+    Apple,
+    /// Random characters of the chosen classes
+    Characters,
+    /// Random words from a bundled wordlist
+    Diceware,
+}
+
+/// The character classes of `characters` passwords. Password rules decide
+/// the characters themselves, so classes can't be asked for with them.
+fn classes(
+    requested: Option<Vec<CharClass>>,
+    rules: Option<&PasswordRules>,
+) -> Result<Vec<CharClass>> {
+    match (requested, rules) {
+        (Some(_), Some(_)) => Err(IpassError::InvalidInput(
+            "--classes can't be used with password rules (from --rules or bundled for the url), which decide the characters"
+                .to_owned(),
+        )),
+        (requested, _) => Ok(requested.unwrap_or_else(|| CharClass::ALL.to_vec())),
+    }
+}
+
+pub async fn generate(args: GenerateArgs, profile: &Profile) -> Result<()> {
+    let url = args
+        .url
+        .as_deref()
+        .or(args.save.as_ref().map(|save| save[0].as_str()));
+    let rules = match (&args.rules, url) {
+        (Some(rules), _) => Some(PasswordRules::parse(rules)?),
+        (None, Some(url)) => PasswordRules::for_url(url)?,
+        (None, None) => None,
+    };
+    let classes = classes(args.classes, rules.as_ref())?;
+
+    let password = match (args.style, &rules) {
+        (Style::Apple, None) => generate::apple(),
+        (Style::Apple, Some(rules)) => {
+            let password = generate::apple();
+            if rules.check(&password) {
+                password
+            } else {
+                debug!("Password rules don't allow Apple style passwords, generating characters");
+                generate::with_rules(rules, args.length)?
+            }
+        }
+        (Style::Characters, None) => generate::characters(args.length, &classes)?,
+        (Style::Characters, Some(rules)) => generate::with_rules(rules, args.length)?,
+        (Style::Diceware, rules) => {
+            let password = generate::diceware(args.words, &args.separator)?;
+            if rules.as_ref().is_some_and(|rules| !rules.check(&password)) {
+                return Err(IpassError::InvalidInput(
+                    "the passphrase doesn't satisfy the password rules, use `--style characters`"
+                        .to_owned(),
+                ));
+            }
+            password
+        }
+    };
+    output::print_value(&password, false)?;
+
+    if let Some([url, username]) = args.save.as_deref() {
+        let overwrite = save::Overwrite::from_args(args.no_overwrite, args.yes);
+        auth::retry_after_auth(profile, args.port, || {
+            let password = password.clone();
+            async move {
+                let client =
+                    Client::connect(profile, args.port).with_session(profile.load_session()?);
+                let plan = client.plan_save(url, username).await?;
+                let outcome =
+                    save::store(&client, plan, url, username, password, overwrite).await?;
+                save::report(outcome)
+            }
+        })
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classes_default_to_all() {
+        assert_eq!(classes(None, None).unwrap(), CharClass::ALL);
+        let rules = PasswordRules::parse("minlength: 8; required: digit;").unwrap();
+        assert_eq!(classes(None, Some(&rules)).unwrap(), CharClass::ALL);
+    }
+
+    #[test]
+    fn test_classes_are_not_dropped_for_rules() {
+        let requested = vec![CharClass::Lower, CharClass::Digits];
+        assert_eq!(classes(Some(requested.clone()), None).unwrap(), requested);
+
+        let rules = PasswordRules::for_url("https://apple.com").unwrap();
+        assert!(matches!(
+            classes(Some(requested), rules.as_ref()),
+            Err(IpassError::InvalidInput(_))
+        ));
+    }
+}
@@ -1,45 +1,61 @@
-use std::net::UdpSocket;
-
-use serde_json::json;
-use tokio::io;
-
-use crate::{config::PassConfig, types::*, GetArgs};
+use ipass::client::Client;
+use ipass::config::Profile;
+use ipass::error::{IpassError, Result};
+use ipass::output::{self, Field};
+use ipass::util;
+
+use crate::{clip, GetArgs};
+
+/// The user name given on the command line, or the default for the domain of `url` if any.
+pub fn username_or_setting(
+    username: Option<String>,
+    url: &str,
+    profile: &Profile,
+) -> Option<String> {
+    username.or_else(|| profile.settings.username_for(url).map(str::to_owned))
+}
 
-pub async fn get(args: GetArgs) -> io::Result<()> {
-    let config = match PassConfig::load() {
-        Ok(config) => config,
-        Err(err) => return Err(err),
-    };
+/// The user name given on the command line, or the default for the domain of `url`.
+pub fn username_or_default(
+    username: Option<String>,
+    url: &str,
+    profile: &Profile,
+) -> Result<String> {
+    username_or_setting(username, url, profile).ok_or_else(|| {
+        let domain = util::domain(url);
+        let command = format!(
+            "{} config set usernames.{} <username>",
+            crate::my_cli(),
+            domain
+        );
+        IpassError::InvalidInput(format!(
+            "no user name given and no default for {}, please pass one or run `{}`",
+            domain, command
+        ))
+    })
+}
 
-    let req = json!(GetLoginNamesForURLReq {
-        cmd: Cmd::GetPasswordForLoginName,
-        tab_id: 0,
-        frame_id: 0,
-        url: args.url.clone(),
-        payload: GetLoginPasswordForURLPayload {
-            qid: "CmdGetPassword4LoginName".to_owned(),
-            smsg: SMSGReq {
-                tid: config.username,
-                sdata: ActURLUser {
-                    act: Action::Search,
-                    url: args.url.clone(),
-                    username: args.username,
-                },
-            },
+pub async fn get(args: GetArgs, profile: &Profile) -> Result<()> {
+    let username = username_or_default(args.username, &args.url, profile)?;
+
+    let client = Client::connect(profile, args.port).with_session(profile.load_session()?);
+    let entries = client.get_password(&args.url, &username).await?;
+
+    match args
+        .field
+        .or((args.raw || args.clip).then_some(Field::Password))
+    {
+        Some(field) => {
+            let entry = util::single(entries, "account")?;
+            let value = entry.field(field);
+            if args.clip {
+                clip::copy(&value, &profile.settings)?;
+            } else {
+                output::print_value(&value, args.raw)?;
+            }
         }
-    });
-
-    let socket = UdpSocket::bind("127.0.0.1:0")?;
-    socket.send_to(
-        req.to_string().as_bytes(),
-        format!("127.0.0.1:{}", args.port),
-    )?;
-
-    let mut buf = [0; 65536];
-    let (len, _) = socket.recv_from(&mut buf)?;
-    let res = serde_json::from_slice::<GetLoginPasswordForURLRes>(&buf[..len]).unwrap();
-
-    println!("{}", json!(res.payload.smsg.sdata));
+        None => print!("{}", profile.settings.output().entries(&entries)?.as_str()),
+    }
 
     Ok(())
 }
@@ -1,46 +1,50 @@
-use std::net::UdpSocket;
-
-use serde_json::json;
-use tokio::io;
-
-use crate::{config::PassConfig, types::*, ListArgs};
-
-pub async fn list(args: ListArgs) -> io::Result<()> {
-    let config = match PassConfig::load() {
-        Ok(config) => config,
-        Err(err) => return Err(err),
-    };
-
-    let req = json!(GetLoginNamesForURLReq {
-        cmd: Cmd::GetLoginNamesForURL,
-        tab_id: 1,
-        frame_id: 1,
-        url: args.url.clone(),
-        payload: GetLoginNamesForURLPayload {
-            qid: "CmdGetLoginNames4URL".to_owned(),
-            smsg: SMSGReq {
-                tid: config.username,
-                sdata: ActURL {
-                    act: Action::GhostSearch,
-                    url: args.url.clone(),
-                },
-            },
-        }
-    });
-
-    let socket = UdpSocket::bind("127.0.0.1:0").expect("bind to address");
-    socket
-        .send_to(
-            req.to_string().as_bytes(),
-            format!("127.0.0.1:{}", args.port),
-        )
-        .expect("send request to socket");
-
-    let mut buf = [0; 65536];
-    let (len, _) = socket.recv_from(&mut buf).expect("receive data");
-    let res = serde_json::from_slice::<GetLoginNamesForURLRes>(&buf[..len]).unwrap();
-
-    println!("{}", json!(res.payload.smsg.sdata.entries));
+use ipass::client::Client;
+use ipass::config::Profile;
+use ipass::error::Result;
+use ipass::output::Tabular;
+use ipass::types::Tab;
+use serde::Serialize;
+use zeroize::Zeroizing;
+
+use crate::ListArgs;
+
+/// An account without its password, which the helper doesn't send when listing.
+#[derive(Serialize)]
+struct LoginName {
+    user: String,
+    sites: Vec<String>,
+}
+
+impl Tabular for LoginName {
+    const COLUMNS: &'static [&'static str] = &["user", "sites"];
+    const PLAIN: usize = 0;
+
+    fn row(&self) -> Zeroizing<Vec<String>> {
+        Zeroizing::new(vec![self.user.clone(), self.sites.join(",")])
+    }
+}
+
+/// Tab and frame listing has always claimed to come from, unlike the other commands.
+const LIST_TAB: Tab = Tab {
+    tab_id: 1,
+    frame_id: 1,
+};
+
+pub async fn list(args: ListArgs, profile: &Profile) -> Result<()> {
+    let client = Client::connect(profile, args.port)
+        .with_tab(LIST_TAB)
+        .with_session(profile.load_session()?);
+    let entries = client
+        .list_logins(&args.url)
+        .await?
+        .into_iter()
+        .map(|entry| LoginName {
+            user: entry.user,
+            sites: entry.sites,
+        })
+        .collect::<Vec<_>>();
+
+    print!("{}", profile.settings.output().entries(&entries)?.as_str());
 
     Ok(())
 }
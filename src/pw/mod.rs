@@ -1,6 +1,10 @@
+mod generate;
 mod get;
 mod list;
 mod save;
+mod update;
+pub use crate::pw::generate::*;
 pub use crate::pw::get::*;
 pub use crate::pw::list::*;
 pub use crate::pw::save::*;
+pub use crate::pw::update::*;
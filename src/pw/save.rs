@@ -1,88 +1,217 @@
-use log::debug;
-use serde_json::json;
-use tokio::io;
-use tokio::net::UdpSocket;
-
-use crate::config::PassConfig;
-use crate::types::*;
-use crate::SaveArgs;
-
-pub async fn save(args: SaveArgs) -> io::Result<()> {
-    let config = match PassConfig::load() {
-        Ok(config) => config,
-        Err(err) => return Err(err),
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+
+use ipass::client::Client;
+use ipass::config::Profile;
+use ipass::error::{IpassError, Result};
+use ipass::secret::SecretString;
+use ipass::types::{SaveOutcome, SavePlan};
+use ipass::util;
+use zeroize::Zeroizing;
+
+use crate::{PasswordInput, SaveArgs};
+
+/// The password to save, from the arguments, stdin, a file or a prompt.
+pub fn read_password(arg: Option<&str>, input: &PasswordInput) -> Result<SecretString> {
+    let password = if let Some(password) = arg {
+        SecretString::new(password.to_owned())
+    } else if input.password_stdin {
+        let mut input = Zeroizing::new(String::new());
+        io::stdin().read_to_string(&mut input)?;
+        without_newline(&input)
+    } else if let Some(path) = &input.password_file {
+        let input = Zeroizing::new(fs::read_to_string(path)?);
+        without_newline(&input)
+    } else {
+        let password = prompt("Password: ")?;
+        if password != prompt("Confirm password: ")? {
+            return Err(IpassError::InvalidInput(
+                "passwords do not match".to_owned(),
+            ));
+        }
+        password
     };
 
-    // Stage 1: Save the login name
-    let stage1_req = json!(SaveStage1Req {
-        cmd: Cmd::SaveStage1LoginName,
-        tab_id: 0,
-        frame_id: 0,
-        payload: SaveStage1Payload {
-            qid: "CmdSaveStage1LoginName".to_owned(),
-            smsg: SMSGReq {
-                tid: config.username.clone(),
-                sdata: SaveStage1Data {
-                    act: Action::Search,
-                    url: args.url.clone(),
-                    username: args.username.clone(),
-                },
-            },
+    if password.is_empty() {
+        return Err(IpassError::InvalidInput("password is empty".to_owned()));
+    }
+    Ok(password)
+}
+
+fn prompt(prompt: &str) -> Result<SecretString> {
+    rpassword::prompt_password(prompt)
+        .map(SecretString::new)
+        .map_err(|err| {
+            IpassError::InvalidInput(format!(
+                "cannot prompt for the password ({}), use --password-stdin or --password-file",
+                err
+            ))
+        })
+}
+
+/// Drops the line break ending the input, which is not part of the password.
+fn without_newline(input: &str) -> SecretString {
+    let line = input.strip_suffix('\n').unwrap_or(input);
+    SecretString::new(line.strip_suffix('\r').unwrap_or(line).to_owned())
+}
+
+pub async fn save(args: SaveArgs, password: SecretString, profile: &Profile) -> Result<()> {
+    let client = Client::connect(profile, args.port).with_session(profile.load_session()?);
+    let plan = client.plan_save(&args.url, &args.username).await?;
+
+    if args.dry_run {
+        print!("{}", profile.settings.output().record(&plan)?.as_str());
+        eprintln!("{}", plan);
+        return match plan {
+            SavePlan::Rejected { status } => Err(IpassError::Helper(status)),
+            SavePlan::RequiresAuth => Err(IpassError::PermissionDenied(plan.to_string())),
+            SavePlan::Create | SavePlan::Replace => Ok(()),
+        };
+    }
+
+    let overwrite = Overwrite::from_args(args.no_overwrite, args.yes);
+    let outcome = store(
+        &client,
+        plan,
+        &args.url,
+        &args.username,
+        password,
+        overwrite,
+    )
+    .await?;
+    print!("{}", profile.settings.output().record(&outcome)?.as_str());
+    report(outcome)
+}
+
+/// Whether saving may replace the password of an existing account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overwrite {
+    /// Ask on the terminal, and refuse when nobody can answer
+    Ask,
+    /// Replace without asking, for `--yes`
+    Always,
+    /// Refuse, for `--no-overwrite`
+    Never,
+}
+
+impl Overwrite {
+    pub fn from_args(no_overwrite: bool, yes: bool) -> Self {
+        match (no_overwrite, yes) {
+            (true, _) => Self::Never,
+            (false, true) => Self::Always,
+            (false, false) => Self::Ask,
         }
-    });
-
-    let socket = UdpSocket::bind("127.0.0.1:0").await?;
-    socket
-        .send_to(
-            stage1_req.to_string().as_bytes(),
-            format!("127.0.0.1:{}", args.port),
-        )
-        .await?;
-
-    let mut buf = [0; 65536];
-    let (len, _) = socket.recv_from(&mut buf).await?;
-    debug!(
-        "Received stage1 response: {}",
-        std::str::from_utf8(&buf[..len]).unwrap()
-    );
-    let stage1_res: SaveStage1Res = serde_json::from_slice(&buf[..len]).unwrap();
-    debug!("Stage1 response: {:#?}", stage1_res);
-
-    // Stage 2: Save the password
-    let save_req = json!(SavePasswordReq {
-        cmd: Cmd::NewAccount4URL,
-        tab_id: 0,
-        frame_id: 0,
-        payload: SavePasswordPayload {
-            qid: "CmdNewAccount4URL".to_owned(),
-            smsg: SMSGReq {
-                tid: config.username,
-                sdata: SavePasswordData {
-                    act: Action::MaybeAdd,
-                    url: String::new(),
-                    usr: String::new(),
-                    pwd: String::new(),
-                    nurl: args.url,
-                    nusr: args.username,
-                    npwd: args.password,
-                },
-            },
+    }
+}
+
+/// Saves the password of the account for `url` and `username` as `plan`ned,
+/// replacing an existing one only as `overwrite` allows.
+pub async fn store(
+    client: &Client,
+    plan: SavePlan,
+    url: &str,
+    username: &str,
+    password: SecretString,
+    overwrite: Overwrite,
+) -> Result<SaveOutcome> {
+    if plan == SavePlan::Replace {
+        confirm_replace(
+            overwrite,
+            io::stdin().is_terminal(),
+            url,
+            username,
+            |prompt| util::confirm(prompt, false),
+        )?;
+    }
+    client.apply_save(plan, url, username, password).await
+}
+
+/// Fails unless the password of `username` saved for `url` may be replaced,
+/// `ask`ing when stdin is a terminal. Scripts, e.g. feeding the password
+/// through stdin, can't answer and have to pass `--yes`.
+fn confirm_replace(
+    overwrite: Overwrite,
+    interactive: bool,
+    url: &str,
+    username: &str,
+    ask: impl FnOnce(&str) -> io::Result<bool>,
+) -> Result<()> {
+    let refuse = |reason: &str| {
+        Err(IpassError::Exists(format!(
+            "{} already has a password saved for {}, {}",
+            username, url, reason
+        )))
+    };
+    match overwrite {
+        Overwrite::Always => Ok(()),
+        Overwrite::Never => refuse("not overwriting it"),
+        Overwrite::Ask if !interactive => {
+            refuse("not replacing it without confirmation, please pass --yes")
+        }
+        Overwrite::Ask => {
+            let prompt = format!("Replace the password of {} saved for {}?", username, url);
+            match ask(&prompt)? {
+                true => Ok(()),
+                false => Err(IpassError::Cancelled),
+            }
         }
-    });
-
-    socket
-        .send_to(
-            save_req.to_string().as_bytes(),
-            format!("127.0.0.1:{}", args.port),
-        )
-        .await?;
-
-    let (len, _) = socket.recv_from(&mut buf).await?;
-    debug!(
-        "Received save response: {}",
-        std::str::from_utf8(&buf[..len]).unwrap()
-    );
-    println!("Password saved successfully");
-
-    Ok(())
+    }
+}
+
+/// Fails for outcomes that didn't save the password, and tells about the others on stderr.
+pub fn report(outcome: SaveOutcome) -> Result<()> {
+    match outcome {
+        SaveOutcome::Rejected { status } => Err(IpassError::Helper(status)),
+        SaveOutcome::RequiresAuth => Err(IpassError::PermissionDenied(outcome.to_string())),
+        _ => {
+            eprintln!("{}", outcome);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn confirm(overwrite: Overwrite, interactive: bool, answer: bool) -> Result<()> {
+        confirm_replace(overwrite, interactive, "github.com", "octocat", |prompt| {
+            assert_eq!(
+                prompt,
+                "Replace the password of octocat saved for github.com?"
+            );
+            Ok(answer)
+        })
+    }
+
+    #[test]
+    fn test_overwrite_from_args() {
+        assert_eq!(Overwrite::from_args(false, false), Overwrite::Ask);
+        assert_eq!(Overwrite::from_args(false, true), Overwrite::Always);
+        assert_eq!(Overwrite::from_args(true, false), Overwrite::Never);
+    }
+
+    #[test]
+    fn test_confirm_replace() {
+        assert!(confirm(Overwrite::Always, false, false).is_ok());
+        assert!(confirm(Overwrite::Ask, true, true).is_ok());
+        assert!(matches!(
+            confirm(Overwrite::Ask, true, false),
+            Err(IpassError::Cancelled)
+        ));
+        assert!(matches!(
+            confirm(Overwrite::Never, true, true),
+            Err(IpassError::Exists(_))
+        ));
+    }
+
+    #[test]
+    fn test_replace_without_terminal_needs_yes() {
+        let err = confirm_replace(Overwrite::Ask, false, "github.com", "octocat", |_| {
+            panic!("asked without a terminal")
+        })
+        .unwrap_err();
+        assert!(matches!(err, IpassError::Exists(_)));
+        assert!(err.to_string().contains("--yes"), "{}", err);
+    }
 }
@@ -0,0 +1,114 @@
+use ipass::client::Client;
+use ipass::config::Profile;
+use ipass::error::{IpassError, Result};
+use ipass::types::{AccountUpdate, UpdateOutcome};
+
+use crate::pw::{read_password, username_or_default};
+use crate::UpdateArgs;
+
+/// The new values of the account, reading the new password once before any retry.
+pub fn read_update(args: &UpdateArgs) -> Result<AccountUpdate> {
+    let new_password =
+        args.new_password || args.input.password_stdin || args.input.password_file.is_some();
+    if !new_password && args.new_username.is_none() && args.new_url.is_none() {
+        return Err(IpassError::InvalidInput(
+            "nothing to update, please pass --new-password, --new-username or --new-url".to_owned(),
+        ));
+    }
+    Ok(AccountUpdate {
+        url: args.new_url.clone(),
+        username: args.new_username.clone(),
+        password: new_password
+            .then(|| read_password(None, &args.input))
+            .transpose()?,
+    })
+}
+
+pub async fn update(args: UpdateArgs, update: AccountUpdate, profile: &Profile) -> Result<()> {
+    let username = username_or_default(args.username, &args.url, profile)?;
+    let client = Client::connect(profile, args.port).with_session(profile.load_session()?);
+    let outcome = client.update_account(&args.url, &username, update).await?;
+
+    print!("{}", profile.settings.output().record(&outcome)?.as_str());
+    match outcome {
+        UpdateOutcome::Rejected { status } => Err(IpassError::Helper(status)),
+        _ => {
+            eprintln!("{}", outcome);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::PasswordInput;
+
+    use super::*;
+
+    fn update_args() -> UpdateArgs {
+        UpdateArgs {
+            port: None,
+            url: "github.com".to_owned(),
+            username: None,
+            new_password: false,
+            input: PasswordInput {
+                password_stdin: false,
+                password_file: None,
+            },
+            new_username: None,
+            new_url: None,
+        }
+    }
+
+    #[test]
+    fn test_read_update_requires_a_change() {
+        assert!(matches!(
+            read_update(&update_args()),
+            Err(IpassError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_update_keeps_what_is_not_changed() {
+        let update = read_update(&UpdateArgs {
+            new_username: Some("monalisa".to_owned()),
+            ..update_args()
+        })
+        .unwrap();
+        assert_eq!(update.username.as_deref(), Some("monalisa"));
+        assert_eq!(update.url, None);
+        assert!(update.password.is_none());
+    }
+
+    #[test]
+    fn test_read_update_reads_the_password_file() {
+        let path = env::temp_dir().join(format!("ipass-update-{}", std::process::id()));
+        fs::write(&path, "hunter3\n").unwrap();
+        let update = read_update(&UpdateArgs {
+            input: PasswordInput {
+                password_stdin: false,
+                password_file: Some(path.clone()),
+            },
+            ..update_args()
+        });
+        fs::remove_file(&path).unwrap();
+        assert_eq!(update.unwrap().password.unwrap().as_str(), "hunter3");
+    }
+
+    #[test]
+    fn test_read_update_rejects_an_empty_password_file() {
+        let path = env::temp_dir().join(format!("ipass-update-empty-{}", std::process::id()));
+        fs::write(&path, "\n").unwrap();
+        let update = read_update(&UpdateArgs {
+            input: PasswordInput {
+                password_stdin: false,
+                password_file: Some(path.clone()),
+            },
+            ..update_args()
+        });
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(update, Err(IpassError::InvalidInput(_))));
+    }
+}
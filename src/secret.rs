@@ -0,0 +1,276 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::{Deref, DerefMut, Range};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use log::debug;
+use rand::RngCore;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::Zeroize;
+
+static MLOCK: AtomicBool = AtomicBool::new(false);
+
+/// Locked pages and how many live secrets are on each of them.
+static LOCKED_PAGES: Mutex<PageCounts> = Mutex::new(PageCounts(BTreeMap::new()));
+
+/// Locks the memory of secrets created from now on so it is never swapped to disk.
+pub fn enable_mlock() {
+    MLOCK.store(true, Ordering::Relaxed);
+}
+
+/// Locks don't nest, a page is locked once and unlocked when the last secret
+/// on it is dropped, so secrets sharing a page don't unlock each other.
+#[derive(Debug, Default)]
+struct PageCounts(BTreeMap<usize, usize>);
+
+impl PageCounts {
+    /// Counts a secret on `pages`, locking the ones not locked yet. Fails
+    /// without counting anything if a page can't be locked.
+    fn lock(&mut self, pages: Range<usize>, mut lock_page: impl FnMut(usize) -> bool) -> bool {
+        for page in pages.clone() {
+            let count = self.0.entry(page).or_insert(0);
+            if *count == 0 && !lock_page(page) {
+                self.0.remove(&page);
+                self.unlock(pages.start..page, |_| {});
+                return false;
+            }
+            *count += 1;
+        }
+        true
+    }
+
+    /// Stops counting a secret on `pages`, unlocking the ones no other secret is on.
+    fn unlock(&mut self, pages: Range<usize>, mut unlock_page: impl FnMut(usize)) {
+        for page in pages {
+            if let Some(count) = self.0.get_mut(&page) {
+                *count -= 1;
+                if *count == 0 {
+                    self.0.remove(&page);
+                    unlock_page(page);
+                }
+            }
+        }
+    }
+}
+
+fn page_size() -> usize {
+    static PAGE_SIZE: OnceLock<usize> = OnceLock::new();
+    // SAFETY: sysconf has no preconditions.
+    *PAGE_SIZE.get_or_init(|| {
+        usize::try_from(unsafe { libc::sysconf(libc::_SC_PAGESIZE) }).unwrap_or(4096)
+    })
+}
+
+/// Indexes of the pages holding `len` bytes at `ptr`.
+fn pages(ptr: *const u8, len: usize) -> Range<usize> {
+    let start = ptr as usize;
+    start / page_size()..(start + len).div_ceil(page_size())
+}
+
+fn mlock(ptr: *const u8, len: usize) -> bool {
+    if len == 0 || !MLOCK.load(Ordering::Relaxed) {
+        return false;
+    }
+    let mut pages_locked = LOCKED_PAGES.lock().unwrap_or_else(|err| err.into_inner());
+    pages_locked.lock(pages(ptr, len), |page| {
+        // SAFETY: the page belongs to the allocation of a live buffer owned by the caller.
+        let locked =
+            unsafe { libc::mlock((page * page_size()) as *const libc::c_void, page_size()) } == 0;
+        if !locked {
+            debug!(
+                "Failed to lock secret memory: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        locked
+    })
+}
+
+fn munlock(ptr: *const u8, len: usize) {
+    let mut pages_locked = LOCKED_PAGES.lock().unwrap_or_else(|err| err.into_inner());
+    pages_locked.unlock(pages(ptr, len), |page| {
+        // SAFETY: the page was locked by `mlock` and no live secret is on it anymore.
+        unsafe { libc::munlock((page * page_size()) as *const libc::c_void, page_size()) };
+    });
+}
+
+/// Bytes that are wiped from memory when dropped and never printed.
+pub struct SecretBytes {
+    bytes: Vec<u8>,
+    locked: bool,
+}
+
+impl SecretBytes {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        let locked = mlock(bytes.as_ptr(), bytes.capacity());
+        Self { bytes, locked }
+    }
+
+    /// Creates `len` random bytes.
+    pub fn random(len: usize) -> Self {
+        let mut secret = Self::new(vec![0; len]);
+        rand::rng().fill_bytes(&mut secret);
+        secret
+    }
+}
+
+impl Deref for SecretBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl DerefMut for SecretBytes {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+}
+
+impl Clone for SecretBytes {
+    fn clone(&self) -> Self {
+        Self::new(self.bytes.clone())
+    }
+}
+
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        let (ptr, len) = (self.bytes.as_ptr(), self.bytes.capacity());
+        self.bytes.zeroize();
+        if self.locked {
+            munlock(ptr, len);
+        }
+    }
+}
+
+impl fmt::Debug for SecretBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretBytes(***)")
+    }
+}
+
+/// A string that is wiped from memory when dropped and never printed.
+#[derive(Default)]
+pub struct SecretString {
+    string: String,
+    locked: bool,
+}
+
+impl SecretString {
+    pub fn new(string: String) -> Self {
+        let locked = mlock(string.as_ptr(), string.capacity());
+        Self { string, locked }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.string
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(string: String) -> Self {
+        Self::new(string)
+    }
+}
+
+impl Deref for SecretString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.string
+    }
+}
+
+impl PartialEq for SecretString {
+    fn eq(&self, other: &Self) -> bool {
+        self.string == other.string
+    }
+}
+
+impl Clone for SecretString {
+    fn clone(&self) -> Self {
+        Self::new(self.string.clone())
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        let (ptr, len) = (self.string.as_ptr(), self.string.capacity());
+        self.string.zeroize();
+        if self.locked {
+            munlock(ptr, len);
+        }
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"***\"")
+    }
+}
+
+impl Serialize for SecretString {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        self.string.serialize(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretString {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        String::deserialize(d).map(Self::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_is_redacted() {
+        let secret = SecretString::new("hunter2".to_owned());
+        assert_eq!(format!("{:?}", secret), "\"***\"");
+        assert_eq!(
+            format!("{:?}", SecretBytes::new(b"hunter2".to_vec())),
+            "SecretBytes(***)"
+        );
+    }
+
+    #[test]
+    fn test_shared_pages_stay_locked_until_the_last_secret_is_dropped() {
+        let mut counts = PageCounts::default();
+        let mut locked = Vec::new();
+        assert!(counts.lock(1..3, |page| {
+            locked.push(page);
+            true
+        }));
+        assert!(counts.lock(2..4, |page| {
+            locked.push(page);
+            true
+        }));
+        assert_eq!(locked, [1, 2, 3]);
+
+        let mut unlocked = Vec::new();
+        counts.unlock(1..3, |page| unlocked.push(page));
+        assert_eq!(unlocked, [1]);
+        counts.unlock(2..4, |page| unlocked.push(page));
+        assert_eq!(unlocked, [1, 2, 3]);
+        assert!(counts.0.is_empty());
+    }
+
+    #[test]
+    fn test_failed_lock_counts_nothing() {
+        let mut counts = PageCounts::default();
+        assert!(counts.lock(1..2, |_| true));
+        assert!(!counts.lock(0..3, |page| page != 2));
+        assert_eq!(counts.0, BTreeMap::from([(1, 1)]));
+    }
+
+    #[test]
+    fn test_serde_is_transparent() {
+        let secret: SecretString = serde_json::from_str("\"hunter2\"").unwrap();
+        assert_eq!(secret.as_str(), "hunter2");
+        assert_eq!(serde_json::to_string(&secret).unwrap(), "\"hunter2\"");
+    }
+}
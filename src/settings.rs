@@ -0,0 +1,102 @@
+use serde_json::{json, Value};
+
+use ipass::error::{IpassError, Result};
+
+use crate::{ConfigKeyArgs, ConfigSetArgs};
+use ipass::config::{ConfigFile, Settings};
+
+/// Settings that hold a single value, `usernames` holds one per domain.
+const KEYS: [&str; 9] = [
+    "host",
+    "port",
+    "socket",
+    "timeout",
+    "output",
+    "helper",
+    "clip_command",
+    "clip_paste_command",
+    "clip_timeout",
+];
+
+/// Splits `usernames.<domain>` into the setting and the domain.
+fn parse_key(key: &str) -> Result<(&str, Option<&str>)> {
+    match key.split_once('.') {
+        Some(("usernames", domain)) if !domain.is_empty() => Ok(("usernames", Some(domain))),
+        None if KEYS.contains(&key) || key == "usernames" => Ok((key, None)),
+        _ => Err(IpassError::InvalidInput(format!(
+            "unknown setting `{}`, expected one of {}, usernames or usernames.<domain>",
+            key,
+            KEYS.join(", ")
+        ))),
+    }
+}
+
+/// The settings of the config file with the built-in defaults filled in.
+fn effective(config: &ConfigFile) -> Result<Value> {
+    Ok(serde_json::to_value(
+        config.settings.clone().or(&Settings::builtin()),
+    )?)
+}
+
+pub fn list() -> Result<()> {
+    let config = ConfigFile::load()?;
+    println!("{}", effective(&config)?);
+    Ok(())
+}
+
+pub fn get(args: ConfigKeyArgs) -> Result<()> {
+    let (key, domain) = parse_key(&args.key)?;
+    let settings = effective(&ConfigFile::load()?)?;
+    let value = match domain {
+        Some(domain) => settings.get(key).and_then(|value| value.get(domain)),
+        None => settings.get(key),
+    };
+    match value {
+        Some(Value::String(value)) => println!("{}", value),
+        Some(value) => println!("{}", value),
+        None => {
+            return Err(IpassError::NotFound(format!(
+                "setting `{}` is not set",
+                args.key
+            )))
+        }
+    }
+    Ok(())
+}
+
+pub fn set(args: ConfigSetArgs) -> Result<()> {
+    let (key, domain) = parse_key(&args.key)?;
+    let value = match key {
+        "port" | "timeout" | "clip_timeout" => {
+            serde_json::from_str(&args.value).unwrap_or(json!(args.value))
+        }
+        _ => json!(args.value),
+    };
+    update(&args.key, |settings| match domain {
+        Some(domain) => settings[key][domain] = value,
+        None => settings[key] = value,
+    })
+}
+
+pub fn unset(args: ConfigKeyArgs) -> Result<()> {
+    let (key, domain) = parse_key(&args.key)?;
+    update(&args.key, |settings| {
+        let parent = match domain {
+            Some(_) => settings.get_mut(key),
+            None => Some(settings),
+        };
+        if let Some(map) = parent.and_then(Value::as_object_mut) {
+            map.remove(domain.unwrap_or(key));
+        }
+    })
+}
+
+/// Edits the settings of the config file as JSON, and saves them if they are still valid.
+fn update(key: &str, edit: impl FnOnce(&mut Value)) -> Result<()> {
+    let mut config = ConfigFile::load()?;
+    let mut settings = serde_json::to_value(&config.settings)?;
+    edit(&mut settings);
+    config.settings = serde_json::from_value(settings)
+        .map_err(|err| IpassError::InvalidInput(format!("invalid value for `{}`: {}", key, err)))?;
+    config.save()
+}
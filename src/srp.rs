@@ -1,7 +1,37 @@
+use std::sync::LazyLock;
+
 use num::{BigInt, Num};
 
 use num_bigint::Sign;
 use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::secret::SecretBytes;
+
+/// Prime of the 3072-bit SRP group from RFC 5054, appendix A.
+pub static GROUP_PRIME: LazyLock<BigInt> = LazyLock::new(|| {
+    BigInt::from_str_radix(
+        concat!(
+            "FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74",
+            "020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F1437",
+            "4FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7ED",
+            "EE386BFB5A899FA5AE9F24117C4B1FE649286651ECE45B3DC2007CB8A163BF05",
+            "98DA48361C55D39A69163FA8FD24CF5F83655D23DCA3AD961C62F356208552BB",
+            "9ED529077096966D670C354E4ABC9804F1746C08CA18217C32905E462E36CE3B",
+            "E39E772C180E86039B2783A2EC07A28FB5C55DF06F4C52C9DE2BCBF695581718",
+            "3995497CEA956AE515D2261898FA051015728E5A8AAAC42DAD33170D04507A33",
+            "A85521ABDF1CBA64ECFB850458DBEF0A8AEA71575D060C7DB3970F85A6E1E4C7",
+            "ABF5AE8CDB0933D71E8C94E04A25619DCEE3D2261AD2EE6BF12FFA06D98A0864",
+            "D87602733EC86A64521F2B18177B200CBBE117577A615D6C770988C0BAD946E2",
+            "08E24FA074E5AB3143DB5BFCE0FD108E4B82D120A93AD2CAFFFFFFFFFFFFFFFF",
+        ),
+        16,
+    )
+    .expect("valid hexadecimal prime")
+});
+
+/// Generator of the 3072-bit SRP group.
+pub static GROUP_GENERATOR: LazyLock<BigInt> = LazyLock::new(|| BigInt::from(5u32));
 
 pub fn pad(data: &[u8], len: usize) -> Vec<u8> {
     let mut padded = vec![0; len];
@@ -14,12 +44,9 @@ pub fn pre_master_secret(
     client_private_key: &[u8],
     server_public_key: &Vec<u8>,
     username: &String,
-    password: &String,
+    password: &str,
     salt: &Vec<u8>,
-) -> Vec<u8> {
-    let group_prime = BigInt::from_str_radix( r#"FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F14374FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7EDEE386BFB5A899FA5AE9F24117C4B1FE649286651ECE45B3DC2007CB8A163BF0598DA48361C55D39A69163FA8FD24CF5F83655D23DCA3AD961C62F356208552BB9ED529077096966D670C354E4ABC9804F1746C08CA18217C32905E462E36CE3BE39E772C180E86039B2783A2EC07A28FB5C55DF06F4C52C9DE2BCBF6955817183995497CEA956AE515D2261898FA051015728E5A8AAAC42DAD33170D04507A33A85521ABDF1CBA64ECFB850458DBEF0A8AEA71575D060C7DB3970F85A6E1E4C7ABF5AE8CDB0933D71E8C94E04A25619DCEE3D2261AD2EE6BF12FFA06D98A0864D87602733EC86A64521F2B18177B200CBBE117577A615D6C770988C0BAD946E208E24FA074E5AB3143DB5BFCE0FD108E4B82D120A93AD2CAFFFFFFFFFFFFFFFF"#, 16).unwrap();
-    let group_generator = BigInt::from_bytes_be(num_bigint::Sign::Plus, &[5]);
-
+) -> SecretBytes {
     let padded_client_pub = pad(client_public_key.as_slice(), 3072 >> 3);
     let padded_server_pub = pad(server_public_key.as_slice(), 3072 >> 3);
 
@@ -28,40 +55,36 @@ pub fn pre_master_secret(
     let u = hasher.finalize().to_vec();
 
     let mut hasher = Sha256::new();
-    hasher.update(group_prime.to_bytes_be().1);
-    let padded_generator = pad(group_generator.to_bytes_be().1.as_slice(), 3072 >> 3);
+    hasher.update(GROUP_PRIME.to_bytes_be().1);
+    let padded_generator = pad(GROUP_GENERATOR.to_bytes_be().1.as_slice(), 3072 >> 3);
     hasher.update(&padded_generator);
     let k = hasher.finalize().to_vec();
 
     let mut hasher = Sha256::new();
-    hasher.update(format!("{}:{}", username, password).as_bytes());
-    let hash = hasher.finalize().to_vec();
+    hasher.update(Zeroizing::new(format!("{}:{}", username, password)).as_bytes());
+    let hash = Zeroizing::new(hasher.finalize().to_vec());
 
-    let salted = [salt.as_slice(), hash.as_slice()].concat();
+    let salted = Zeroizing::new([salt.as_slice(), hash.as_slice()].concat());
 
     let mut hasher = Sha256::new();
-    hasher.update(&salted);
-    let salted_sha = hasher.finalize().to_vec();
-    let salted_bigint = BigInt::from_bytes_be(num_bigint::Sign::Plus, &salted_sha.to_vec());
+    hasher.update(salted.as_slice());
+    let salted_sha = Zeroizing::new(hasher.finalize().to_vec());
+    let salted_bigint = BigInt::from_bytes_be(num_bigint::Sign::Plus, &salted_sha);
 
     let kgx = BigInt::from_bytes_be(num_bigint::Sign::Plus, server_public_key)
         - BigInt::from_bytes_be(num_bigint::Sign::Plus, &k)
-            * powmod(
-                &group_generator.clone(),
-                &salted_bigint,
-                &group_prime.clone(),
-            );
+            * powmod(&GROUP_GENERATOR, &salted_bigint, &GROUP_PRIME);
 
     let pms = powmod(
         &kgx,
         &(BigInt::from_bytes_be(num_bigint::Sign::Plus, client_private_key)
             + BigInt::from_bytes_be(num_bigint::Sign::Plus, &u) * salted_bigint),
-        &group_prime.clone(),
+        &GROUP_PRIME,
     );
 
     let mut hasher = Sha256::new();
-    hasher.update(pms.to_bytes_be().1);
-    hasher.finalize().to_vec()
+    hasher.update(Zeroizing::new(pms.to_bytes_be().1));
+    SecretBytes::new(hasher.finalize().to_vec())
 }
 
 pub fn modm(a: &BigInt, b: &BigInt) -> BigInt {
@@ -93,17 +116,14 @@ pub fn compute_m(
     salt: &Vec<u8>,
     client_public_key: &Vec<u8>,
     server_public_key: &Vec<u8>,
-    shared_key: &Vec<u8>,
+    shared_key: &[u8],
 ) -> Vec<u8> {
-    let group_prime = BigInt::from_str_radix( r#"FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F14374FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7EDEE386BFB5A899FA5AE9F24117C4B1FE649286651ECE45B3DC2007CB8A163BF0598DA48361C55D39A69163FA8FD24CF5F83655D23DCA3AD961C62F356208552BB9ED529077096966D670C354E4ABC9804F1746C08CA18217C32905E462E36CE3BE39E772C180E86039B2783A2EC07A28FB5C55DF06F4C52C9DE2BCBF6955817183995497CEA956AE515D2261898FA051015728E5A8AAAC42DAD33170D04507A33A85521ABDF1CBA64ECFB850458DBEF0A8AEA71575D060C7DB3970F85A6E1E4C7ABF5AE8CDB0933D71E8C94E04A25619DCEE3D2261AD2EE6BF12FFA06D98A0864D87602733EC86A64521F2B18177B200CBBE117577A615D6C770988C0BAD946E208E24FA074E5AB3143DB5BFCE0FD108E4B82D120A93AD2CAFFFFFFFFFFFFFFFF"#, 16).unwrap();
-    let group_generator = BigInt::from_bytes_be(num_bigint::Sign::Plus, &[5]);
-
     let mut hasher = Sha256::new();
-    hasher.update(group_prime.to_bytes_be().1);
+    hasher.update(GROUP_PRIME.to_bytes_be().1);
     let n = hasher.finalize().to_vec();
 
     let mut hasher = Sha256::new();
-    let padded_generator = pad(group_generator.to_bytes_be().1.as_slice(), 3072 >> 3);
+    let padded_generator = pad(GROUP_GENERATOR.to_bytes_be().1.as_slice(), 3072 >> 3);
     hasher.update(&padded_generator);
     let g = hasher.finalize().to_vec();
 
@@ -113,16 +133,27 @@ pub fn compute_m(
 
     let xor_ng: Vec<u8> = n.iter().enumerate().map(|(idx, b)| b ^ g[idx]).collect();
 
-    let mut final_data = Vec::new();
+    let mut final_data = Zeroizing::new(Vec::new());
     final_data.extend_from_slice(&xor_ng);
     final_data.extend_from_slice(i.as_slice());
     final_data.extend_from_slice(salt.as_slice());
     final_data.extend_from_slice(client_public_key.as_slice());
     final_data.extend_from_slice(server_public_key.as_slice());
-    final_data.extend_from_slice(shared_key.as_slice());
+    final_data.extend_from_slice(shared_key);
 
     let mut hasher = Sha256::new();
-    hasher.update(&final_data);
+    hasher.update(final_data.as_slice());
     let final_hash = hasher.finalize();
     final_hash.to_vec()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_is_the_3072_bit_one() {
+        assert_eq!(GROUP_PRIME.bits(), 3072);
+        assert_eq!(*GROUP_GENERATOR, BigInt::from(5u32));
+    }
+}
@@ -1,6 +1,7 @@
 use std::fmt::Debug;
 
 use super::b64;
+use super::Browser;
 use super::Cmd;
 use super::MsgType;
 use super::SecretSessionVersion;
@@ -25,7 +26,7 @@ pub struct Message<PA: Debug + Serialize + DeserializeOwned> {
     #[serde(rename = "PAKE", with = "b64")]
     pub pake: PA,
     #[serde(rename = "HSTBRSR")]
-    pub hstbrsr: String,
+    pub hstbrsr: Browser,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -54,7 +55,7 @@ pub struct VerifyPakeRes {
     pub msg: MsgType,
     #[serde(rename = "ErrCode")]
     pub error_code: Option<u8>,
-    #[serde(rename = "HAMK")]
+    #[serde(rename = "HAMK", default)]
     pub hamk: String,
 }
 
@@ -115,7 +116,7 @@ mod tests {
                     ver: "1.0".to_owned(),
                     proto: [1].to_vec(),
                 },
-                hstbrsr: "Arc".to_owned(),
+                hstbrsr: Browser::Arc,
             },
             cmd: Cmd::HandShake,
         };
@@ -1,22 +1,76 @@
 pub mod auth;
+pub mod session;
 
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{de, de::DeserializeOwned, Deserialize, Deserializer, Serialize};
 use serde_repr::*;
 
-#[repr(u8)]
-#[derive(Debug, Serialize_repr, Deserialize_repr, PartialEq)]
+use crate::secret::SecretString;
+use crate::util;
+use session::Sealed;
+
+/// Commands of the helper's native messaging protocol that ipass sends or
+/// receives. Others the helper knows, like ending an operation, launching the
+/// Passwords app or listing one time codes without filling one, are left out
+/// until their payloads and replies are known.
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, Serialize_repr, Deserialize_repr, PartialEq)]
 pub enum Cmd {
     HandShake = 2,
     GetLoginNamesForURL = 4,
     GetPasswordForLoginName = 5,
     NewAccount4URL = 6,
     SaveStage1LoginName = 7,
+    /// Notification that password filling was turned off
+    PasswordsDisabled = 9,
+    /// Notification that the session is gone and `auth` must run again
+    ReloginNeeded = 10,
+    /// Notification that iCloud Passwords was enabled or disabled
+    ICloudPasswordsStateChange = 12,
+    GetCapabilities = 14,
+    /// Notification that a one time code arrived, e.g. by SMS
+    OneTimeCodeAvailable = 15,
     DidFillOneTimeCode = 17,
 }
 
+pub const STATUS_SUCCESS: i64 = 0;
+/// Status the helper reports when no account matches.
+pub const STATUS_NO_RESULTS: i64 = 3;
+/// Status the helper reports when saving an account it already has.
+pub const STATUS_DUPLICATE_ITEM: i64 = 7;
+/// Status the helper reports for requests made with an unknown session.
+pub const STATUS_INVALID_SESSION: i64 = 9;
+
+/// Host browser presented to the helper when establishing a session.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, clap::ValueEnum)]
+pub enum Browser {
+    #[default]
+    Arc,
+    Chrome,
+    Chromium,
+    Firefox,
+    Edge,
+    Brave,
+    Opera,
+    Vivaldi,
+}
+
+/// Browser tab and frame a request claims to come from.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct Tab {
+    #[serde(rename = "tabId")]
+    pub tab_id: u32,
+    #[serde(rename = "frameId")]
+    pub frame_id: u32,
+}
+
 #[repr(u8)]
 #[derive(Debug, Serialize_repr, Deserialize_repr, PartialEq)]
 pub enum Action {
+    Delete = 0,
+    Update = 1,
     Search = 2,
     MaybeAdd = 4,
     GhostSearch = 5,
@@ -41,10 +95,8 @@ pub enum SecretSessionVersion {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GetLoginNamesForURLReq<P: Serialize + DeserializeOwned> {
     pub cmd: Cmd,
-    #[serde(rename = "tabId")]
-    pub tab_id: u32,
-    #[serde(rename = "frameId")]
-    pub frame_id: u32,
+    #[serde(flatten)]
+    pub tab: Tab,
     pub url: String,
     #[serde(with = "jsonstring")]
     pub payload: P,
@@ -53,31 +105,144 @@ pub struct GetLoginNamesForURLReq<P: Serialize + DeserializeOwned> {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GetOTPReq<P: Serialize + DeserializeOwned> {
     pub cmd: Cmd,
-    #[serde(rename = "tabId")]
-    pub tab_id: u32,
-    #[serde(rename = "frameId")]
-    pub frame_id: u32,
+    #[serde(flatten)]
+    pub tab: Tab,
     #[serde(with = "jsonstring")]
     pub payload: P,
 }
 
+/// Request without a payload, e.g. [`Cmd::GetCapabilities`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CmdReq {
+    pub cmd: Cmd,
+    #[serde(flatten)]
+    pub tab: Tab,
+}
+
+/// Message the helper sends on its own, which may arrive instead of a reply.
+#[derive(Debug, PartialEq)]
+pub enum Notification {
+    /// Password filling was turned off
+    PasswordsDisabled,
+    /// The session is gone and `auth` must run again
+    ReloginNeeded,
+    /// iCloud Passwords was enabled or disabled
+    ICloudPasswordsStateChange(PasswordsState),
+    /// A one time code arrived, e.g. by SMS
+    OneTimeCodeAvailable(OneTimeCodeAvailable),
+}
+
+impl Notification {
+    pub fn cmd(&self) -> Cmd {
+        match self {
+            Self::PasswordsDisabled => Cmd::PasswordsDisabled,
+            Self::ReloginNeeded => Cmd::ReloginNeeded,
+            Self::ICloudPasswordsStateChange(_) => Cmd::ICloudPasswordsStateChange,
+            Self::OneTimeCodeAvailable(_) => Cmd::OneTimeCodeAvailable,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Notification {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Message {
+            cmd: Cmd,
+            #[serde(default)]
+            payload: Option<serde_json::Value>,
+        }
+
+        let message = Message::deserialize(deserializer)?;
+        let payload = message
+            .payload
+            .unwrap_or_else(|| serde_json::Value::Object(Default::default()));
+        match message.cmd {
+            Cmd::PasswordsDisabled => Ok(Self::PasswordsDisabled),
+            Cmd::ReloginNeeded => Ok(Self::ReloginNeeded),
+            Cmd::ICloudPasswordsStateChange => serde_json::from_value(payload)
+                .map(Self::ICloudPasswordsStateChange)
+                .map_err(de::Error::custom),
+            Cmd::OneTimeCodeAvailable => serde_json::from_value(payload)
+                .map(Self::OneTimeCodeAvailable)
+                .map_err(de::Error::custom),
+            cmd => Err(de::Error::custom(format!(
+                "command {:?} is not a notification",
+                cmd
+            ))),
+        }
+    }
+}
+
+/// Payload of [`Notification::ICloudPasswordsStateChange`], unknown fields are kept as they are.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PasswordsState {
+    /// Whether iCloud Passwords is enabled now
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_json::Value>,
+}
+
+/// Payload of [`Notification::OneTimeCodeAvailable`], unknown fields are kept as they are.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct OneTimeCodeAvailable {
+    /// Where the code came from, as in the `source` of [`OTPEntry`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Domain the code is for, if the helper knows it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CapabilitiesRes {
+    pub cmd: Cmd,
+    pub payload: Capabilities,
+}
+
+/// What the helper supports, unknown capabilities are kept as they are.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Capabilities {
+    #[serde(
+        rename = "canFillOneTimeCodes",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub can_fill_one_time_codes: Option<bool>,
+    #[serde(
+        rename = "operatingSystem",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub operating_system: Option<OperatingSystem>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OperatingSystem {
+    pub name: String,
+    #[serde(rename = "majorVersion")]
+    pub major_version: u32,
+    #[serde(rename = "minorVersion")]
+    pub minor_version: u32,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GetLoginNamesForURLRes {
     pub cmd: Cmd,
-    #[serde(rename = "tabId")]
-    pub tab_id: u32,
-    #[serde(rename = "frameId")]
-    pub frame_id: u32,
+    #[serde(flatten)]
+    pub tab: Tab,
     pub payload: GetLoginNamesForURLPayloadRes,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GetLoginPasswordForURLRes {
     pub cmd: Cmd,
-    #[serde(rename = "tabId")]
-    pub tab_id: u32,
-    #[serde(rename = "frameId")]
-    pub frame_id: u32,
+    #[serde(flatten)]
+    pub tab: Tab,
     pub payload: GetLoginPasswordForURLPayloadRes,
     pub url: String,
 }
@@ -85,10 +250,8 @@ pub struct GetLoginPasswordForURLRes {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GetOTPForURLRes {
     pub cmd: Cmd,
-    #[serde(rename = "tabId")]
-    pub tab_id: u32,
-    #[serde(rename = "frameId")]
-    pub frame_id: u32,
+    #[serde(flatten)]
+    pub tab: Tab,
     pub payload: GetOTPForURLPayloadRes,
 }
 
@@ -98,7 +261,20 @@ pub struct LoginEntry {
     pub user: String,
     pub sites: Vec<String>,
     #[serde(alias = "PWD")]
-    pub password: String,
+    pub password: SecretString,
+}
+
+impl LoginEntry {
+    /// The site of the account `url` refers to, which is how the helper knows
+    /// the account, falling back to the first site and then to `url` itself.
+    pub fn site_for<'a>(&'a self, url: &'a str) -> &'a str {
+        let domain = util::domain(url);
+        self.sites
+            .iter()
+            .find(|site| util::domain(site) == domain)
+            .or(self.sites.first())
+            .map_or(url, String::as_str)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -112,7 +288,7 @@ pub struct OTPEntry {
     pub username: String,
     pub source: String,
     pub domain: String,
-    pub code: String,
+    pub code: SecretString,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -197,42 +373,46 @@ pub struct GetLoginNamesForURLPayloadRes {
     pub smsg: SMSGRes<LoginEntries>,
 }
 
+/// Encrypted message to the helper, created with [`session::Session::smsg`].
 #[derive(Debug, Serialize, Deserialize)]
-pub struct SMSGReq<T: Serialize + DeserializeOwned> {
+pub struct SMSGReq<T> {
     #[serde(rename = "TID")]
     pub tid: String,
-    #[serde(rename = "SDATA", with = "crypto")]
-    pub sdata: T,
+    #[serde(rename = "SDATA")]
+    pub sdata: Sealed<T>,
 }
 
+/// Encrypted reply of the helper, read with [`session::Session::open`].
 #[derive(Debug, Serialize, Deserialize)]
-pub struct SMSGRes<T: Serialize + DeserializeOwned> {
+pub struct SMSGRes<T> {
     #[serde(rename = "TID")]
     pub tid: String,
-    #[serde(rename = "SDATA", with = "crypto")]
-    pub sdata: T,
+    #[serde(rename = "SDATA")]
+    pub sdata: Sealed<T>,
 }
 
 pub mod b64 {
     use base64::prelude::*;
     use log::{debug, log_enabled};
-    use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
-    use serde_json::json;
+    use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
 
     pub fn serialize<S: Serializer, V: Serialize>(v: &V, s: S) -> Result<S::Ok, S::Error> {
-        let base64 = BASE64_STANDARD.encode(json!(v).to_string());
-        String::serialize(&base64, s)
+        let json = serde_json::to_value(v).map_err(ser::Error::custom)?;
+        String::serialize(&BASE64_STANDARD.encode(json.to_string()), s)
     }
 
-    pub fn deserialize<'de, D: Deserializer<'de>, DE: std::fmt::Debug + DeserializeOwned>(
+    pub fn deserialize<'de, D: Deserializer<'de>, DE: std::fmt::Debug + de::DeserializeOwned>(
         d: D,
     ) -> Result<DE, D::Error> {
         let base64 = String::deserialize(d)?;
         if log_enabled!(log::Level::Debug) {
             debug!("Decoding base64: {}", base64);
         }
-        let b64decoded = BASE64_STANDARD.decode(base64).unwrap();
-        let de: DE = serde_json::from_slice(&b64decoded).unwrap();
+        let b64decoded = BASE64_STANDARD
+            .decode(base64)
+            .map_err(|err| de::Error::custom(format!("invalid base64: {}", err)))?;
+        let de: DE = serde_json::from_slice(&b64decoded)
+            .map_err(|err| de::Error::custom(format!("invalid JSON in base64: {}", err)))?;
         if log_enabled!(log::Level::Debug) {
             debug!("Deserialized: {:?}", de);
         }
@@ -242,83 +422,24 @@ pub mod b64 {
 
 mod jsonstring {
     use log::{debug, log_enabled};
-    use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
-    use serde_json::json;
+    use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
 
     pub fn serialize<S: Serializer, V: Serialize>(v: &V, s: S) -> Result<S::Ok, S::Error> {
-        json!(v).to_string().serialize(s)
+        serde_json::to_value(v)
+            .map_err(ser::Error::custom)?
+            .to_string()
+            .serialize(s)
     }
 
-    pub fn deserialize<'de, D: Deserializer<'de>, DE: DeserializeOwned>(
+    pub fn deserialize<'de, D: Deserializer<'de>, DE: de::DeserializeOwned>(
         d: D,
     ) -> Result<DE, D::Error> {
-        let base64 = String::deserialize(d)?;
+        let json = String::deserialize(d)?;
         if log_enabled!(log::Level::Debug) {
-            debug!("Deserializing JSON string: {}", base64);
+            debug!("Deserializing JSON string: {}", json);
         }
-        let de: DE = serde_json::from_slice(base64.as_bytes()).unwrap();
-        Ok(de)
-    }
-}
-
-mod crypto {
-    use crate::config::PassConfig;
-    use aead::{array::typenum, Aead, KeyInit};
-    use aes_gcm::aes::Aes128;
-    use aes_gcm::AesGcm;
-    use base64::prelude::*;
-    use log::{debug, log_enabled};
-    use rand::RngCore;
-    use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
-    use serde_json::json;
-    type Aes256GcmWith16BitNonce = AesGcm<Aes128, typenum::U16>;
-
-    pub fn serialize<S: Serializer, V: Serialize>(v: &V, s: S) -> Result<S::Ok, S::Error> {
-        let config = match PassConfig::load() {
-            Ok(config) => config,
-            Err(err) => return Err(serde::ser::Error::custom(err)),
-        };
-        let key = config.decryption_key();
-
-        let sdata = json!(v);
-
-        let mut iv = [0u8; 16];
-        let mut rng = rand::thread_rng();
-        rng.fill_bytes(&mut iv);
-
-        let cipher = Aes256GcmWith16BitNonce::new_from_slice(&key[..]).unwrap();
-
-        let mut encrypted = cipher
-            .encrypt(&iv.into(), sdata.to_string().as_bytes())
-            .expect("encrypt data");
-        encrypted = [encrypted.as_slice(), &iv[..16]].concat();
-
-        String::serialize(&BASE64_STANDARD.encode(&encrypted), s)
-    }
-
-    pub fn deserialize<'de, D: Deserializer<'de>, DE: DeserializeOwned>(
-        d: D,
-    ) -> Result<DE, D::Error> {
-        let config = match PassConfig::load() {
-            Ok(config) => config,
-            Err(err) => return Err(serde::de::Error::custom(err)),
-        };
-        let key = config.decryption_key();
-
-        let cipher = Aes256GcmWith16BitNonce::new_from_slice(&key[..]).unwrap();
-        let sdata = BASE64_STANDARD
-            .decode(String::deserialize(d)?)
-            .expect("base64 decode payload sdata");
-        let iv = sdata[..16].try_into().expect("parse first 16 bytes to iv");
-        let decrypted = cipher.decrypt(iv, &sdata[16..]).expect("decrypt response");
-        if log_enabled!(log::Level::Debug) {
-            debug!(
-                "Decrypted string: {}",
-                std::str::from_utf8(&decrypted).unwrap()
-            );
-        }
-        let de: DE = serde_json::from_slice(&decrypted).unwrap();
-        Ok(de)
+        serde_json::from_str(&json)
+            .map_err(|err| de::Error::custom(format!("invalid JSON in string: {}", err)))
     }
 }
 
@@ -335,10 +456,8 @@ pub struct SaveStage1Data {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SaveStage1Req {
     pub cmd: Cmd,
-    #[serde(rename = "tabId")]
-    pub tab_id: i32,
-    #[serde(rename = "frameId")]
-    pub frame_id: i32,
+    #[serde(flatten)]
+    pub tab: Tab,
     pub payload: SaveStage1Payload,
 }
 
@@ -365,18 +484,16 @@ pub struct SaveStage1ResPayload {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SaveStage1ResData {
     #[serde(rename = "STATUS")]
-    pub status: i32,
-    #[serde(rename = "RequiresUserAuthenticationToFill")]
+    pub status: i64,
+    #[serde(rename = "RequiresUserAuthenticationToFill", default)]
     pub requires_user_authentication_to_fill: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SavePasswordReq {
     pub cmd: Cmd,
-    #[serde(rename = "tabId")]
-    pub tab_id: i32,
-    #[serde(rename = "frameId")]
-    pub frame_id: i32,
+    #[serde(flatten)]
+    pub tab: Tab,
     pub payload: SavePasswordPayload,
 }
 
@@ -397,7 +514,46 @@ pub struct SavePasswordData {
     #[serde(rename = "USR")]
     pub usr: String,
     #[serde(rename = "PWD")]
-    pub pwd: String,
+    pub pwd: SecretString,
+    #[serde(rename = "NURL")]
+    pub nurl: String,
+    #[serde(rename = "NUSR")]
+    pub nusr: String,
+    #[serde(rename = "NPWD")]
+    pub npwd: SecretString,
+}
+
+/// Deleting an account. No delete command of the helper is known, so this
+/// is `CmdNewAccount4URL` with `ACT` set to [`Action::Delete`] and the account
+/// as saving finds it, with the new values left empty. The shape mirrors
+/// [`SavePasswordReq`] and has not been checked against a capture of the
+/// browser extension deleting an account.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteAccountReq {
+    pub cmd: Cmd,
+    #[serde(flatten)]
+    pub tab: Tab,
+    pub payload: DeleteAccountPayload,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteAccountPayload {
+    #[serde(rename = "QID")]
+    pub qid: String,
+    #[serde(rename = "SMSG")]
+    pub smsg: SMSGReq<DeleteAccountData>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteAccountData {
+    #[serde(rename = "ACT")]
+    pub act: Action,
+    #[serde(rename = "URL")]
+    pub url: String,
+    #[serde(rename = "USR")]
+    pub usr: String,
+    #[serde(rename = "PWD")]
+    pub pwd: SecretString,
     #[serde(rename = "NURL")]
     pub nurl: String,
     #[serde(rename = "NUSR")]
@@ -406,6 +562,21 @@ pub struct SavePasswordData {
     pub npwd: String,
 }
 
+impl DeleteAccountData {
+    /// Deletes `account`, known to the helper by `site`.
+    pub fn new(site: &str, account: LoginEntry) -> Self {
+        Self {
+            act: Action::Delete,
+            url: site.to_owned(),
+            usr: account.user,
+            pwd: account.password,
+            nurl: String::new(),
+            nusr: String::new(),
+            npwd: String::new(),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SavePasswordRes {
     pub cmd: Cmd,
@@ -415,5 +586,517 @@ pub struct SavePasswordRes {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SavePasswordResPayload {
     #[serde(rename = "SMSG")]
-    pub smsg: SMSGRes<String>,
+    pub smsg: SMSGRes<StatusData>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatusData {
+    #[serde(rename = "STATUS")]
+    pub status: i64,
+}
+
+/// What saving a password would do, from the accounts of the url and stage 1 of saving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "plan", rename_all = "kebab-case")]
+pub enum SavePlan {
+    /// A new account would be created
+    Create,
+    /// The password of an existing account would be replaced
+    Replace,
+    /// The account can only be changed after the user authenticates on the Mac
+    RequiresAuth,
+    /// The helper refused in stage 1, with its status code
+    Rejected { status: i64 },
+}
+
+impl SavePlan {
+    /// The plan from the reply to stage 1, and whether the account was `listed`
+    /// for the url, as the helper may not report accounts it only knows by
+    /// another url in stage 1.
+    pub fn from_stage1(status: i64, requires_auth: bool, listed: bool) -> Self {
+        let exists = match status {
+            STATUS_SUCCESS => true,
+            STATUS_NO_RESULTS => listed,
+            status => return Self::Rejected { status },
+        };
+        if requires_auth {
+            Self::RequiresAuth
+        } else if exists {
+            Self::Replace
+        } else {
+            Self::Create
+        }
+    }
+}
+
+impl fmt::Display for SavePlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Create => f.write_str("Would create a new account"),
+            Self::Replace => f.write_str("Would replace the password of the existing account"),
+            Self::RequiresAuth => {
+                f.write_str("Would need authenticating on the Mac before changing this account")
+            }
+            Self::Rejected { status } => write!(
+                f,
+                "Password manager would reject the password with status {}",
+                status
+            ),
+        }
+    }
+}
+
+/// What saving a password did, from the status codes of both save stages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", rename_all = "kebab-case")]
+pub enum SaveOutcome {
+    /// A new account was created
+    Created,
+    /// The password of an existing account was replaced
+    Updated,
+    /// The account already has this password
+    Exists,
+    /// The helper refused to save, with its status code
+    Rejected { status: i64 },
+    /// The account can only be changed after the user authenticates on the Mac
+    RequiresAuth,
+}
+
+impl SaveOutcome {
+    /// The outcome of the status the helper replied to stage 2 with, for an
+    /// account that already `exists` or not.
+    pub fn from_status(status: i64, exists: bool) -> Self {
+        match status {
+            STATUS_SUCCESS if exists => Self::Updated,
+            STATUS_SUCCESS => Self::Created,
+            STATUS_DUPLICATE_ITEM => Self::Exists,
+            status => Self::Rejected { status },
+        }
+    }
+}
+
+impl fmt::Display for SaveOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Created => f.write_str("Password saved"),
+            Self::Updated => f.write_str("Password updated"),
+            Self::Exists => f.write_str("Password is already saved"),
+            Self::Rejected { status } => write!(
+                f,
+                "Password manager rejected the password with status {}",
+                status
+            ),
+            Self::RequiresAuth => f.write_str(
+                "Password manager requires authenticating on the Mac before changing this account",
+            ),
+        }
+    }
+}
+
+/// New values for an existing account, `None` keeps the current one.
+#[derive(Debug, Default, Clone)]
+pub struct AccountUpdate {
+    pub url: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<SecretString>,
+}
+
+impl AccountUpdate {
+    /// What the update changes of `account`, known to the helper by `site`.
+    pub fn changes(&self, site: &str, account: &LoginEntry) -> Vec<Change> {
+        [
+            (
+                Change::Url,
+                self.url.as_deref().is_some_and(|url| url != site),
+            ),
+            (
+                Change::Username,
+                self.username
+                    .as_ref()
+                    .is_some_and(|user| *user != account.user),
+            ),
+            (
+                Change::Password,
+                self.password
+                    .as_ref()
+                    .is_some_and(|password| *password != account.password),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(change, changed)| changed.then_some(change))
+        .collect()
+    }
+}
+
+/// A part of an account changed by an update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Change {
+    Url,
+    Username,
+    Password,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Url => "url",
+            Self::Username => "user name",
+            Self::Password => "password",
+        })
+    }
+}
+
+/// What updating an account did.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", rename_all = "kebab-case")]
+pub enum UpdateOutcome {
+    /// The account was changed
+    Updated { changed: Vec<Change> },
+    /// The account already has the new values, nothing was sent
+    Unchanged,
+    /// The helper refused the update, with its status code
+    Rejected { status: i64 },
+}
+
+impl fmt::Display for UpdateOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Updated { changed } => {
+                let changed = changed.iter().map(Change::to_string).collect::<Vec<_>>();
+                write!(f, "Updated the {} of the account", changed.join(" and "))
+            }
+            Self::Unchanged => f.write_str("Account already has these values"),
+            Self::Rejected { status } => write!(
+                f,
+                "Password manager rejected the update with status {}",
+                status
+            ),
+        }
+    }
+}
+
+/// What deleting an account did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", rename_all = "kebab-case")]
+pub enum DeleteOutcome {
+    /// The account was removed
+    Deleted,
+    /// The helper refused to delete, with its status code
+    Rejected { status: i64 },
+    /// The helper reported success but still lists the account
+    StillSaved,
+}
+
+impl DeleteOutcome {
+    /// The outcome of the status the helper replied to a deletion with, and
+    /// whether it still lists the account afterwards.
+    pub fn from_reply(status: i64, still_listed: bool) -> Self {
+        match status {
+            STATUS_SUCCESS if still_listed => Self::StillSaved,
+            STATUS_SUCCESS => Self::Deleted,
+            status => Self::Rejected { status },
+        }
+    }
+}
+
+impl fmt::Display for DeleteOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Deleted => f.write_str("Account deleted"),
+            Self::Rejected { status } => write!(
+                f,
+                "Password manager refused to delete the account with status {}",
+                status
+            ),
+            Self::StillSaved => {
+                f.write_str("Password manager reported deleting the account, but still lists it")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::auth::*;
+    use super::session::Session;
+    use super::*;
+    use crate::client::parse_response;
+    use base64::prelude::*;
+    use proptest::prelude::*;
+    use serde_json::json;
+    use zeroize::Zeroizing;
+
+    /// Parses `buf` as every reply of the helper, which must fail gracefully.
+    fn parse_all(buf: &[u8]) {
+        let _ = parse_response::<GetLoginNamesForURLRes>(buf);
+        let _ = parse_response::<GetLoginPasswordForURLRes>(buf);
+        let _ = parse_response::<GetOTPForURLRes>(buf);
+        let _ = parse_response::<SaveStage1Res>(buf);
+        let _ = parse_response::<SavePasswordRes>(buf);
+        let _ = parse_response::<Response<ChallengeMsg>>(buf);
+        let _ = parse_response::<Response<VerifyMsg>>(buf);
+        let _ = parse_response::<CapabilitiesRes>(buf);
+        let _ = parse_response::<Notification>(buf);
+    }
+
+    #[test]
+    fn test_tab_is_flattened_into_requests() {
+        let req = CmdReq {
+            cmd: Cmd::GetCapabilities,
+            tab: Tab {
+                tab_id: 1,
+                frame_id: 1,
+            },
+        };
+        assert_eq!(
+            serde_json::to_value(&req).unwrap(),
+            json!({"cmd": 14, "tabId": 1, "frameId": 1})
+        );
+    }
+
+    #[test]
+    fn test_notifications_are_typed_by_cmd() {
+        let parse = |value: serde_json::Value| serde_json::from_value::<Notification>(value);
+        assert_eq!(
+            parse(json!({"cmd": 9})).unwrap(),
+            Notification::PasswordsDisabled
+        );
+        assert_eq!(
+            parse(json!({"cmd": 10, "payload": null})).unwrap(),
+            Notification::ReloginNeeded
+        );
+        assert_eq!(
+            parse(json!({"cmd": 12, "payload": {"enabled": false, "reason": "signedOut"}}))
+                .unwrap(),
+            Notification::ICloudPasswordsStateChange(PasswordsState {
+                enabled: Some(false),
+                other: BTreeMap::from([("reason".to_owned(), json!("signedOut"))]),
+            })
+        );
+        let notification =
+            parse(json!({"cmd": 15, "payload": {"source": "sms", "domain": "github.com"}}))
+                .unwrap();
+        assert_eq!(notification.cmd(), Cmd::OneTimeCodeAvailable);
+        assert_eq!(
+            notification,
+            Notification::OneTimeCodeAvailable(OneTimeCodeAvailable {
+                source: Some("sms".to_owned()),
+                domain: Some("github.com".to_owned()),
+                other: BTreeMap::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_replies_are_not_notifications() {
+        for value in [
+            json!({"cmd": 14, "payload": {}}),
+            json!({"cmd": 12, "payload": {"enabled": "yes"}}),
+            json!({"payload": {}}),
+            // Commands ipass doesn't model are unknown rather than guessed.
+            json!({"cmd": 13, "payload": {}}),
+            json!({"cmd": 1984}),
+        ] {
+            assert!(
+                serde_json::from_value::<Notification>(value.clone()).is_err(),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_save_plan_from_stage1() {
+        for (status, requires_auth, listed, plan) in [
+            (STATUS_SUCCESS, false, false, SavePlan::Replace),
+            (STATUS_SUCCESS, false, true, SavePlan::Replace),
+            (STATUS_NO_RESULTS, false, false, SavePlan::Create),
+            (STATUS_NO_RESULTS, false, true, SavePlan::Replace),
+            (STATUS_SUCCESS, true, false, SavePlan::RequiresAuth),
+            (STATUS_NO_RESULTS, true, false, SavePlan::RequiresAuth),
+            (7, true, true, SavePlan::Rejected { status: 7 }),
+        ] {
+            assert_eq!(
+                SavePlan::from_stage1(status, requires_auth, listed),
+                plan,
+                "status {} requires auth {} listed {}",
+                status,
+                requires_auth,
+                listed
+            );
+        }
+    }
+
+    #[test]
+    fn test_save_outcome_from_status() {
+        assert_eq!(
+            SaveOutcome::from_status(STATUS_SUCCESS, false),
+            SaveOutcome::Created
+        );
+        assert_eq!(
+            SaveOutcome::from_status(STATUS_SUCCESS, true),
+            SaveOutcome::Updated
+        );
+        assert_eq!(
+            SaveOutcome::from_status(STATUS_DUPLICATE_ITEM, true),
+            SaveOutcome::Exists
+        );
+        assert_eq!(
+            SaveOutcome::from_status(STATUS_NO_RESULTS, false),
+            SaveOutcome::Rejected {
+                status: STATUS_NO_RESULTS
+            }
+        );
+    }
+
+    #[test]
+    fn test_delete_request_serialization() {
+        let data =
+            DeleteAccountData::new("github.com", login("octocat", &["github.com"], "hunter2"));
+        assert_eq!(
+            serde_json::to_value(&data).unwrap(),
+            json!({
+                "ACT": 0,
+                "URL": "github.com",
+                "USR": "octocat",
+                "PWD": "hunter2",
+                "NURL": "",
+                "NUSR": "",
+                "NPWD": "",
+            })
+        );
+
+        let req = DeleteAccountReq {
+            cmd: Cmd::NewAccount4URL,
+            tab: Tab::default(),
+            payload: DeleteAccountPayload {
+                qid: "CmdNewAccount4URL".to_owned(),
+                smsg: Session::new("u".to_owned(), Zeroizing::new([0; 16]))
+                    .smsg(&data)
+                    .unwrap(),
+            },
+        };
+        let value = serde_json::to_value(&req).unwrap();
+        assert_eq!(value["cmd"], 6);
+        assert_eq!(value["payload"]["QID"], "CmdNewAccount4URL");
+        assert_eq!(value["payload"]["SMSG"]["TID"], "u");
+    }
+
+    #[test]
+    fn test_delete_outcome() {
+        assert_eq!(DeleteOutcome::from_reply(0, false), DeleteOutcome::Deleted);
+        assert_eq!(
+            DeleteOutcome::from_reply(0, true),
+            DeleteOutcome::StillSaved
+        );
+        assert_eq!(
+            DeleteOutcome::from_reply(3, false),
+            DeleteOutcome::Rejected { status: 3 }
+        );
+        assert_eq!(
+            serde_json::to_value(DeleteOutcome::StillSaved).unwrap(),
+            json!({"outcome": "still-saved"})
+        );
+        assert_eq!(
+            serde_json::to_value(DeleteOutcome::Deleted).unwrap(),
+            json!({"outcome": "deleted"})
+        );
+        assert_eq!(
+            serde_json::to_value(DeleteOutcome::Rejected { status: 3 }).unwrap(),
+            json!({"outcome": "rejected", "status": 3})
+        );
+        assert_eq!(
+            DeleteOutcome::Rejected { status: 3 }.to_string(),
+            "Password manager refused to delete the account with status 3"
+        );
+    }
+
+    fn login(user: &str, sites: &[&str], password: &str) -> LoginEntry {
+        LoginEntry {
+            user: user.to_owned(),
+            sites: sites.iter().map(|site| site.to_string()).collect(),
+            password: SecretString::new(password.to_owned()),
+        }
+    }
+
+    #[test]
+    fn test_site_for_matches_the_domain_of_the_url() {
+        let account = login("octocat", &["gist.github.com", "github.com"], "hunter2");
+        assert_eq!(account.site_for("https://github.com/login"), "github.com");
+        assert_eq!(account.site_for("gist.github.com"), "gist.github.com");
+        assert_eq!(account.site_for("api.github.com"), "gist.github.com");
+        assert_eq!(
+            login("octocat", &[], "hunter2").site_for("github.com"),
+            "github.com"
+        );
+    }
+
+    #[test]
+    fn test_changes_compare_with_the_account() {
+        let account = login("octocat", &["github.com"], "hunter2");
+        let update = |url: Option<&str>, username: Option<&str>, password: Option<&str>| {
+            AccountUpdate {
+                url: url.map(str::to_owned),
+                username: username.map(str::to_owned),
+                password: password.map(|password| SecretString::new(password.to_owned())),
+            }
+            .changes("github.com", &account)
+        };
+
+        assert_eq!(update(None, None, None), []);
+        assert_eq!(
+            update(Some("github.com"), Some("octocat"), Some("hunter2")),
+            []
+        );
+        assert_eq!(update(Some("gitlab.com"), None, None), [Change::Url]);
+        assert_eq!(
+            update(None, Some("monalisa"), Some("hunter3")),
+            [Change::Username, Change::Password]
+        );
+        assert_eq!(
+            update(Some("gitlab.com"), Some("monalisa"), Some("hunter3")),
+            [Change::Url, Change::Username, Change::Password]
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_random_bytes_do_not_panic(buf in prop::collection::vec(any::<u8>(), 0..512)) {
+            parse_all(&buf);
+        }
+
+        #[test]
+        fn test_random_payload_strings_do_not_panic(data in ".*", bytes in prop::collection::vec(any::<u8>(), 0..64)) {
+            let encoded = BASE64_STANDARD.encode(&bytes);
+            for field in [data.as_str(), encoded.as_str()] {
+                let smsg = json!({"cmd": 4, "payload": {"SMSG": {"TID": "t", "SDATA": field}}});
+                let pake = json!({"cmd": 2, "payload": {"QID": "m0", "PAKE": field}});
+                parse_all(smsg.to_string().as_bytes());
+                parse_all(pake.to_string().as_bytes());
+            }
+        }
+
+        #[test]
+        fn test_random_sdata_is_rejected(bytes in prop::collection::vec(any::<u8>(), 0..64)) {
+            let session = Session::new("t".to_owned(), Zeroizing::new([0; 16]));
+            let smsg: SMSGRes<LoginEntries> =
+                serde_json::from_value(json!({"TID": "t", "SDATA": BASE64_STANDARD.encode(&bytes)}))
+                    .unwrap();
+            prop_assert!(session.open(&smsg).is_err());
+        }
+
+        #[test]
+        fn test_pake_round_trip(tid in ".*", m in ".*") {
+            let message = Message {
+                qid: "m2".to_owned(),
+                pake: VerifyPakeReq { tid: tid.clone(), msg: MsgType::ClientVerification, m: m.clone() },
+                hstbrsr: Browser::Arc,
+            };
+            let parsed: Message<VerifyPakeReq> =
+                serde_json::from_str(&serde_json::to_string(&message).unwrap()).unwrap();
+            prop_assert_eq!(parsed.pake.tid, tid);
+            prop_assert_eq!(parsed.pake.m, m);
+        }
+    }
 }
@@ -0,0 +1,177 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use aead::{array::typenum, Aead, KeyInit};
+use aes_gcm::aes::Aes128;
+use aes_gcm::AesGcm;
+use base64::prelude::*;
+use log::debug;
+use rand::RngCore;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use super::{SMSGReq, SMSGRes};
+use crate::error::{IpassError, Result};
+
+type Aes128GcmWith16ByteNonce = AesGcm<Aes128, typenum::U16>;
+
+const NONCE_LEN: usize = 16;
+
+/// SDATA as it travels on the wire: the AES-128-GCM ciphertext of the JSON
+/// encoded `T` and its nonce, base64 encoded. Requests carry the nonce after the
+/// ciphertext, replies of the helper before it.
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Sealed<T> {
+    data: String,
+    #[serde(skip)]
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> fmt::Debug for Sealed<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Sealed({} bytes)", self.data.len())
+    }
+}
+
+/// A session established with the helper, whose key encrypts SDATA.
+pub struct Session {
+    tid: String,
+    key: Zeroizing<[u8; 16]>,
+}
+
+impl Session {
+    pub fn new(tid: String, key: Zeroizing<[u8; 16]>) -> Self {
+        Self { tid, key }
+    }
+
+    /// The session id sent as `TID`.
+    pub fn tid(&self) -> &str {
+        &self.tid
+    }
+
+    /// Encrypts `sdata` into a message for this session.
+    pub fn smsg<T: Serialize>(&self, sdata: &T) -> Result<SMSGReq<T>> {
+        Ok(SMSGReq {
+            tid: self.tid.clone(),
+            sdata: self.seal(sdata)?,
+        })
+    }
+
+    pub fn seal<T: Serialize>(&self, sdata: &T) -> Result<Sealed<T>> {
+        let plain = Zeroizing::new(serde_json::to_value(sdata)?.to_string());
+
+        let mut iv = [0u8; NONCE_LEN];
+        rand::rng().fill_bytes(&mut iv);
+
+        let encrypted = self
+            .cipher()
+            .encrypt(&iv.into(), plain.as_bytes())
+            .map_err(|_| IpassError::Protocol("failed to encrypt SDATA".to_owned()))?;
+        Ok(Sealed {
+            data: BASE64_STANDARD.encode([encrypted.as_slice(), &iv].concat()),
+            marker: PhantomData,
+        })
+    }
+
+    /// Decrypts a message of the helper, which must be for this session.
+    pub fn open<T: DeserializeOwned>(&self, smsg: &SMSGRes<T>) -> Result<T> {
+        if smsg.tid != self.tid {
+            return Err(IpassError::Protocol(format!(
+                "reply for session {} instead of {}",
+                smsg.tid, self.tid
+            )));
+        }
+        self.unseal(&smsg.sdata)
+    }
+
+    /// Decrypts SDATA, failing with [`IpassError::SessionRejected`] if the key doesn't match.
+    fn unseal<T: DeserializeOwned>(&self, sealed: &Sealed<T>) -> Result<T> {
+        let sdata = BASE64_STANDARD
+            .decode(&sealed.data)
+            .map_err(|err| IpassError::Protocol(format!("SDATA is not base64: {}", err)))?;
+        let (iv, encrypted) = sdata
+            .split_first_chunk::<NONCE_LEN>()
+            .ok_or_else(|| IpassError::Protocol("SDATA is too short".to_owned()))?;
+        let decrypted = self
+            .cipher()
+            .decrypt(&(*iv).into(), encrypted)
+            .map(Zeroizing::new)
+            .map_err(|_| IpassError::SessionRejected)?;
+        debug!("Decrypted {} bytes of SDATA", decrypted.len());
+        serde_json::from_slice(&decrypted)
+            .map_err(|err| IpassError::Protocol(format!("invalid SDATA: {}", err)))
+    }
+
+    fn cipher(&self) -> Aes128GcmWith16ByteNonce {
+        Aes128GcmWith16ByteNonce::new(&(*self.key).into())
+    }
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Session")
+            .field("tid", &self.tid)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn session(key: u8) -> Session {
+        Session::new("tid".to_owned(), Zeroizing::new([key; 16]))
+    }
+
+    /// Seals like the helper does, with the nonce in front of the ciphertext.
+    fn reply(session: &Session, sdata: &Value) -> SMSGRes<Value> {
+        let iv = [7u8; NONCE_LEN];
+        let encrypted = session
+            .cipher()
+            .encrypt(&iv.into(), sdata.to_string().as_bytes())
+            .unwrap();
+        SMSGRes {
+            tid: session.tid.clone(),
+            sdata: Sealed {
+                data: BASE64_STANDARD.encode([&iv, encrypted.as_slice()].concat()),
+                marker: PhantomData,
+            },
+        }
+    }
+
+    #[test]
+    fn test_seal_appends_nonce() {
+        let session = session(1);
+        let smsg = session.smsg(&json!({"ACT": 2})).unwrap();
+        assert_eq!(smsg.tid, "tid");
+
+        let sealed = BASE64_STANDARD.decode(&smsg.sdata.data).unwrap();
+        let (encrypted, iv) = sealed.split_last_chunk::<NONCE_LEN>().unwrap();
+        let decrypted = session.cipher().decrypt(&(*iv).into(), encrypted).unwrap();
+        assert_eq!(decrypted, br#"{"ACT":2}"#);
+    }
+
+    #[test]
+    fn test_open_reply() {
+        let session = session(1);
+        let sdata = json!({"Entries": []});
+        assert_eq!(session.open(&reply(&session, &sdata)).unwrap(), sdata);
+    }
+
+    #[test]
+    fn test_open_with_other_key_is_rejected() {
+        let smsg = reply(&session(1), &json!({}));
+        let err = session(2).open(&smsg).unwrap_err();
+        assert!(matches!(err, IpassError::SessionRejected));
+    }
+
+    #[test]
+    fn test_open_for_other_session_is_a_protocol_error() {
+        let mut smsg = reply(&session(1), &json!({}));
+        smsg.tid = "other".to_owned();
+        let err = session(1).open(&smsg).unwrap_err();
+        assert!(matches!(err, IpassError::Protocol(_)));
+    }
+}
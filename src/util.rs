@@ -1,11 +1,51 @@
-use std::{env, path::Path};
-
-pub fn my_cli() -> String {
-    env::args()
-        .next()
-        .as_ref()
-        .map(Path::new)
-        .and_then(Path::to_str)
-        .map(String::from)
-        .unwrap_or("ipass".to_owned())
+use std::io::{self, BufRead, Write};
+
+use crate::error::{IpassError, Result};
+
+/// The only one of `entries`, `what` names the entries in the error messages.
+pub fn single<T>(entries: Vec<T>, what: &str) -> Result<T> {
+    let count = entries.len();
+    let mut entries = entries.into_iter();
+    match (entries.next(), count) {
+        (Some(entry), 1) => Ok(entry),
+        (None, _) => Err(IpassError::NoMatch(format!("no {} found", what))),
+        _ => Err(IpassError::Ambiguous(format!("{} {}s found", count, what))),
+    }
+}
+
+/// Asks a yes/no question on the terminal, `default` is used for an empty answer.
+pub fn confirm(prompt: &str, default: bool) -> io::Result<bool> {
+    eprint!("{} [{}] ", prompt, if default { "Y/n" } else { "y/N" });
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(match answer.trim().to_lowercase().as_str() {
+        "" => default,
+        answer => answer == "y" || answer == "yes",
+    })
+}
+
+/// The domain of a website url, which may be given without a scheme.
+pub fn domain(url: &str) -> &str {
+    let url = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let host = url.split(['/', '?', '#']).next().unwrap_or(url);
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    host.split(':').next().unwrap_or(host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_requires_one_entry() {
+        assert!(matches!(
+            single(Vec::<&str>::new(), "account"),
+            Err(IpassError::NoMatch(_))
+        ));
+        let err = single(vec!["a", "b"], "account").unwrap_err();
+        assert!(matches!(err, IpassError::Ambiguous(_)));
+        assert_eq!(err.to_string(), "2 accounts found");
+        assert_eq!(single(vec!["a"], "account").unwrap(), "a");
+    }
 }